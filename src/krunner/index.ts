@@ -5,6 +5,10 @@ export const webpackModules: ExtensionWebExports["webpackModules"] = {
     dependencies: [
       { ext: "spacepack", id: "spacepack" },
       { ext: "common", id: "stores" },
+      { id: "discord/Constants" },
+      { id: "discord/utils/HTTPUtils" },
+      { id: "discord/utils/NavigationUtils" },
+      { id: "discord/actions/UserSettingsModalActionCreators" },
       "queryVoiceChannels",
       ':"QUICKSWITCHER_SELECT"'
     ],
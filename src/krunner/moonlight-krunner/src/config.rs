@@ -0,0 +1,278 @@
+use crate::proto::MoonlightResultType;
+use clap::Parser;
+use serde::Deserialize;
+use std::{path::PathBuf, time::SystemTime};
+
+// Discord won't return more messages than this per search
+const MAX_MESSAGE_PAGE_SIZE: u32 = 25;
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+pub struct Args {
+    /// Path to the config file [default: $XDG_CONFIG_HOME/moonlight-krunner/config.toml]
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// D-Bus service name to register, overriding the config file
+    #[arg(long)]
+    pub service_name: Option<String>,
+
+    /// D-Bus object path to serve the runner at, overriding the config file
+    #[arg(long)]
+    pub object_path: Option<String>,
+
+    /// Word that starts commands like `dc find`, overriding the config file
+    #[arg(long)]
+    pub trigger: Option<String>,
+
+    /// Log filter such as `debug` or `moonlight_krunner=trace`, overriding `MOONLIGHT_KRUNNER_LOG` [default: info]
+    #[arg(long)]
+    pub log: Option<String>,
+
+    /// Answer queries from a JSON fixture of users, guilds, channels, messages and pages instead of Discord, for testing
+    #[arg(long, value_name = "FIXTURE")]
+    pub fake_client: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DBusConfig {
+    /// Has to match `X-Plasma-DBusRunner-Service` in the .desktop file
+    pub service_name: String,
+    /// Has to match `X-Plasma-DBusRunner-Path` in the .desktop file
+    pub object_path: String,
+}
+
+impl Default for DBusConfig {
+    fn default() -> Self {
+        Self {
+            service_name: "com.notnite.moonlight-krunner".to_string(),
+            object_path: "/moonlight_krunner".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TriggerConfig {
+    /// Queries starting with this word are treated as commands instead of quick switcher searches
+    pub prefix: String,
+    /// `<prefix> <find> <text>` searches messages
+    pub find: String,
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "dc".to_string(),
+            find: "find".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// How long to wait for Discord to answer a query, in milliseconds
+    pub response: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self { response: 5000 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CategoryConfig {
+    pub default: String,
+    pub messages: String,
+    pub pages: String,
+}
+
+impl Default for CategoryConfig {
+    fn default() -> Self {
+        Self {
+            default: "Discord".to_string(),
+            messages: "Discord Messages".to_string(),
+            pages: "Discord Pages".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IconConfig {
+    /// Icon name used when a result doesn't have one of its own
+    pub fallback: String,
+    /// Icon name used for pages and settings sections
+    pub pages: String,
+}
+
+impl Default for IconConfig {
+    fn default() -> Self {
+        Self {
+            fallback: "com.discord.Discord".to_string(),
+            pages: "preferences-system".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ResultConfig {
+    /// How many quick switcher results to show
+    pub max: u32,
+    /// How many messages to show per page of `dc find`, at most 25
+    pub message_page_size: u32,
+}
+
+impl Default for ResultConfig {
+    fn default() -> Self {
+        Self {
+            max: 5,
+            message_page_size: 10,
+        }
+    }
+}
+
+/// Multipliers applied to each result's relevance, so e.g. users can be ranked above channels
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RankingConfig {
+    pub users: f64,
+    pub guilds: f64,
+    pub group_dms: f64,
+    pub text_channels: f64,
+    pub voice_channels: f64,
+    pub messages: f64,
+    pub pages: f64,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            users: 1.,
+            guilds: 1.,
+            group_dms: 1.,
+            text_channels: 1.,
+            voice_channels: 1.,
+            messages: 1.,
+            pages: 1.,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    pub dbus: DBusConfig,
+    pub triggers: TriggerConfig,
+    pub timeouts: TimeoutConfig,
+    pub categories: CategoryConfig,
+    pub icons: IconConfig,
+    pub results: ResultConfig,
+    pub ranking: RankingConfig,
+}
+
+impl Config {
+    pub fn category(&self, r#type: &MoonlightResultType) -> &str {
+        match r#type {
+            MoonlightResultType::Message => &self.categories.messages,
+            MoonlightResultType::Page => &self.categories.pages,
+            _ => &self.categories.default,
+        }
+    }
+
+    pub fn default_icon(&self, r#type: &MoonlightResultType) -> &str {
+        match r#type {
+            MoonlightResultType::Page => &self.icons.pages,
+            _ => &self.icons.fallback,
+        }
+    }
+
+    pub fn weight(&self, r#type: &MoonlightResultType) -> f64 {
+        match r#type {
+            MoonlightResultType::User => self.ranking.users,
+            MoonlightResultType::Guild => self.ranking.guilds,
+            MoonlightResultType::GroupDM => self.ranking.group_dms,
+            MoonlightResultType::TextChannel => self.ranking.text_channels,
+            MoonlightResultType::VoiceChannel => self.ranking.voice_channels,
+            MoonlightResultType::Message => self.ranking.messages,
+            MoonlightResultType::Page => self.ranking.pages,
+        }
+    }
+}
+
+/// Reads the config file and applies CLI overrides on top, keeping track of when it was last changed
+pub struct ConfigLoader {
+    args: Args,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigLoader {
+    pub fn new(args: Args) -> Self {
+        let path = args.config.clone().unwrap_or_else(default_config_path);
+        Self {
+            args,
+            path,
+            modified: None,
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|x| x.modified())
+            .ok()
+    }
+
+    pub fn load(&mut self) -> eyre::Result<Config> {
+        self.modified = self.modified();
+
+        let mut config: Config = match std::fs::read_to_string(&self.path) {
+            Ok(str) => toml::from_str(&str)?,
+            // Not having a config file is fine, everything has a default
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Some(service_name) = &self.args.service_name {
+            config.dbus.service_name = service_name.clone();
+        }
+        if let Some(object_path) = &self.args.object_path {
+            config.dbus.object_path = object_path.clone();
+        }
+        if let Some(trigger) = &self.args.trigger {
+            config.triggers.prefix = trigger.clone();
+        }
+
+        // Pages are counted in these, so a bigger one would skip whatever Discord didn't return
+        config.results.message_page_size = config
+            .results
+            .message_page_size
+            .clamp(1, MAX_MESSAGE_PAGE_SIZE);
+
+        Ok(config)
+    }
+
+    /// Reloads the config if the file was changed, created or removed since the last load
+    pub fn reload_if_changed(&mut self) -> Option<eyre::Result<Config>> {
+        if self.modified() == self.modified {
+            return None;
+        }
+
+        Some(self.load())
+    }
+}
+
+fn default_config_path() -> PathBuf {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+
+    config_home.join("moonlight-krunner").join("config.toml")
+}
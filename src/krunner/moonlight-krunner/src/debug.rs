@@ -0,0 +1,183 @@
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use zbus::{interface, message::Header, object_server::SignalEmitter};
+use zvariant::Type;
+
+use crate::proto::TimedOut;
+
+const MAX_RECENT_QUERIES: usize = 50;
+// Every KRunner restart gets a new unique name, so the old ones have to go eventually
+const MAX_CLIENTS: usize = 16;
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[derive(Serialize, Type, Clone, Debug)]
+pub struct QueryRecord {
+    /// Unix timestamp in milliseconds
+    timestamp: u64,
+    query: String,
+    /// What the query was parsed as, e.g. "Switch" or "FindMessages"
+    kind: String,
+    results: u32,
+    latency_ms: f64,
+    /// Empty if the query succeeded
+    error: String,
+}
+
+#[derive(Serialize, Type, Clone, Debug)]
+pub struct ClientRecord {
+    /// Unique D-Bus name of whoever called us, usually KRunner
+    name: String,
+    calls: u64,
+    /// Unix timestamp in milliseconds
+    last_seen: u64,
+}
+
+#[derive(Default)]
+struct Stats {
+    recent: VecDeque<QueryRecord>,
+    queries: u64,
+    errors: u64,
+    timeouts: u64,
+    total_latency: Duration,
+    clients: HashMap<String, ClientRecord>,
+}
+
+/// Shared between the runner, which records into it, and the debug interface, which reads it
+#[derive(Clone, Default)]
+pub struct DebugStats(Arc<Mutex<Stats>>);
+
+impl DebugStats {
+    pub fn record_client(&self, header: &Header<'_>) {
+        let Some(sender) = header.sender() else {
+            return;
+        };
+
+        let mut stats = self.0.lock().unwrap();
+        if stats.clients.len() >= MAX_CLIENTS
+            && !stats.clients.contains_key(sender.as_str())
+            && let Some(oldest) = stats
+                .clients
+                .values()
+                .min_by_key(|x| x.last_seen)
+                .map(|x| x.name.clone())
+        {
+            stats.clients.remove(&oldest);
+        }
+
+        let client = stats
+            .clients
+            .entry(sender.to_string())
+            .or_insert_with(|| ClientRecord {
+                name: sender.to_string(),
+                calls: 0,
+                last_seen: 0,
+            });
+        client.calls += 1;
+        client.last_seen = unix_millis(SystemTime::now());
+    }
+
+    pub fn record_query(
+        &self,
+        query: &str,
+        kind: &str,
+        latency: Duration,
+        result: Result<usize, &eyre::Report>,
+    ) {
+        let mut stats = self.0.lock().unwrap();
+        stats.queries += 1;
+        stats.total_latency += latency;
+
+        let (results, error) = match result {
+            Ok(count) => (count as u32, String::new()),
+            Err(e) => {
+                stats.errors += 1;
+                if e.downcast_ref::<TimedOut>().is_some() {
+                    stats.timeouts += 1;
+                }
+                (0, e.to_string())
+            }
+        };
+
+        stats.recent.push_front(QueryRecord {
+            timestamp: unix_millis(SystemTime::now()),
+            query: query.to_string(),
+            kind: kind.to_string(),
+            results,
+            latency_ms: latency.as_secs_f64() * 1000.,
+            error,
+        });
+        stats.recent.truncate(MAX_RECENT_QUERIES);
+    }
+}
+
+/// Troubleshooting interface, e.g.
+/// `busctl --user call com.notnite.moonlight-krunner /moonlight_krunner com.notnite.MoonlightKrunner.Debug RecentQueries`
+pub struct DebugInterface {
+    pub stats: DebugStats,
+}
+
+#[interface(name = "com.notnite.MoonlightKrunner.Debug")]
+impl DebugInterface {
+    /// Most recent queries first
+    async fn recent_queries(&self) -> Vec<QueryRecord> {
+        self.stats
+            .0
+            .lock()
+            .unwrap()
+            .recent
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    async fn connected_clients(&self) -> Vec<ClientRecord> {
+        let stats = self.stats.0.lock().unwrap();
+        let mut clients = stats.clients.values().cloned().collect::<Vec<_>>();
+        clients.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        clients
+    }
+
+    async fn reset(&self, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>) {
+        *self.stats.0.lock().unwrap() = Stats::default();
+
+        // Let anyone watching the properties know they went back to zero
+        let _ = self.query_count_changed(&emitter).await;
+        let _ = self.error_count_changed(&emitter).await;
+        let _ = self.timeout_count_changed(&emitter).await;
+        let _ = self.average_latency_ms_changed(&emitter).await;
+    }
+
+    #[zbus(property)]
+    async fn query_count(&self) -> u64 {
+        self.stats.0.lock().unwrap().queries
+    }
+
+    #[zbus(property)]
+    async fn error_count(&self) -> u64 {
+        self.stats.0.lock().unwrap().errors
+    }
+
+    #[zbus(property)]
+    async fn timeout_count(&self) -> u64 {
+        self.stats.0.lock().unwrap().timeouts
+    }
+
+    #[zbus(property)]
+    async fn average_latency_ms(&self) -> f64 {
+        let stats = self.stats.0.lock().unwrap();
+        if stats.queries == 0 {
+            return 0.;
+        }
+
+        stats.total_latency.as_secs_f64() * 1000. / stats.queries as f64
+    }
+}
@@ -0,0 +1,199 @@
+use crate::proto::MoonlightRequest;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::path::Path;
+use tracing::info;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct FixtureUser {
+    id: String,
+    username: String,
+    global_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct FixtureGuild {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct FixtureChannel {
+    id: String,
+    name: String,
+    guild_id: Option<String>,
+    voice: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct FixtureMessage {
+    id: String,
+    channel_id: String,
+    author: String,
+    content: String,
+}
+
+/// Everything the fake client knows about, loaded from a JSON file
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Fixture {
+    users: Vec<FixtureUser>,
+    guilds: Vec<FixtureGuild>,
+    channels: Vec<FixtureChannel>,
+    messages: Vec<FixtureMessage>,
+    /// Sent as-is in the handshake
+    pages: Vec<Value>,
+    /// Sends every result no matter the limit asked for, like a client that doesn't respect it
+    ignore_limit: bool,
+}
+
+/// Stands in for Discord when running with `--fake-client`, so the runner can be tested without it.
+/// Responses are built as JSON so they go through the same parsing as the real client's.
+pub struct FakeClient {
+    fixture: Fixture,
+}
+
+impl FakeClient {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let fixture = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(Self { fixture })
+    }
+
+    /// Mimics the quick switcher closely enough: case insensitive substring matches,
+    /// with exact matches scoring highest
+    fn score(name: &str, query: &str) -> Option<(f64, i32)> {
+        let name = name.to_lowercase();
+        let query = query.trim().to_lowercase();
+        if query.is_empty() || !name.contains(&query) {
+            return None;
+        }
+
+        if name == query {
+            Some((1., 100))
+        } else {
+            Some((query.len() as f64 / name.len() as f64, 70))
+        }
+    }
+
+    fn search(&self, query: &str, limit: u32) -> Value {
+        let mut results = Vec::new();
+
+        for user in &self.fixture.users {
+            let title = user.global_name.as_ref().unwrap_or(&user.username);
+            let score = Self::score(title, query).or_else(|| Self::score(&user.username, query));
+            if let Some((score, category_relevance)) = score {
+                results.push(json!({
+                    "type": "User",
+                    "id": format!("user:{}", user.id),
+                    "title": title,
+                    "subtitle": user.global_name.as_ref().map(|_| &user.username),
+                    "score": score,
+                    "category_relevance": category_relevance,
+                }));
+            }
+        }
+
+        for guild in &self.fixture.guilds {
+            if let Some((score, category_relevance)) = Self::score(&guild.name, query) {
+                results.push(json!({
+                    "type": "Guild",
+                    "id": format!("guild:{}", guild.id),
+                    "title": guild.name,
+                    "score": score,
+                    "category_relevance": category_relevance,
+                }));
+            }
+        }
+
+        for channel in &self.fixture.channels {
+            if let Some((score, category_relevance)) = Self::score(&channel.name, query) {
+                let guild = self
+                    .fixture
+                    .guilds
+                    .iter()
+                    .find(|guild| Some(&guild.id) == channel.guild_id.as_ref());
+                let r#type = match (&channel.guild_id, channel.voice) {
+                    (None, _) => "GroupDM",
+                    (Some(_), true) => "VoiceChannel",
+                    (Some(_), false) => "TextChannel",
+                };
+
+                results.push(json!({
+                    "type": r#type,
+                    "id": format!("channel:{}", channel.id),
+                    "title": channel.name,
+                    "subtitle": guild.map(|guild| &guild.name),
+                    "score": score,
+                    "category_relevance": category_relevance,
+                }));
+            }
+        }
+
+        let score = |result: &Value| result["score"].as_f64().unwrap_or_default();
+        results.sort_by(|a, b| score(b).total_cmp(&score(a)));
+        if !self.fixture.ignore_limit {
+            results.truncate(limit as usize);
+        }
+        Value::Array(results)
+    }
+
+    fn search_messages(&self, query: &str, limit: u32, offset: u32) -> Value {
+        let query = query.to_lowercase();
+        let results = self
+            .fixture
+            .messages
+            .iter()
+            .filter(|message| message.content.to_lowercase().contains(&query))
+            .skip(offset as usize)
+            .take(limit as usize)
+            .enumerate()
+            .map(|(i, message)| {
+                let channel = self
+                    .fixture
+                    .channels
+                    .iter()
+                    .find(|channel| channel.id == message.channel_id);
+                let subtitle = match channel {
+                    Some(channel) => format!("{} in #{}", message.author, channel.name),
+                    None => message.author.clone(),
+                };
+
+                json!({
+                    "type": "Message",
+                    "id": format!("message:{}", message.id),
+                    "title": message.content,
+                    "subtitle": subtitle,
+                    "score": 1. - i as f64 / limit.max(1) as f64,
+                    "category_relevance": 70,
+                })
+            })
+            .collect();
+
+        Value::Array(results)
+    }
+
+    /// Returns what the client would send back, or `None` for requests it doesn't answer
+    pub fn respond(&self, request: &MoonlightRequest) -> Option<Value> {
+        match request {
+            MoonlightRequest::Hello => Some(json!({ "pages": self.fixture.pages })),
+            MoonlightRequest::Search { query, limit } => Some(self.search(query, *limit)),
+            MoonlightRequest::SearchMessages {
+                query,
+                limit,
+                offset,
+            } => Some(self.search_messages(query, *limit, *offset)),
+            MoonlightRequest::Run { id } => {
+                info!(id, "Fake client asked to run result");
+                None
+            }
+            MoonlightRequest::OpenPage { id } => {
+                info!(id, "Fake client asked to open page");
+                None
+            }
+        }
+    }
+}
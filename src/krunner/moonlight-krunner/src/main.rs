@@ -1,14 +1,36 @@
+use clap::Parser;
 use serde::Serialize;
-use std::{collections::HashMap, time::Duration, vec};
-use zbus::{conn::Builder, interface};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+    vec,
+};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
+use zbus::{Connection, conn::Builder, interface, message::Header};
 use zvariant::{OwnedValue, SerializeDict, Type};
 
+mod config;
+mod debug;
+mod fake;
+mod pages;
 mod proto;
+mod query;
 
-struct MoonlightRunner;
+// Discord is already loaded by the time it spawns us, but give it some slack
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_LOG_FILTER: &str = "info";
 
+#[derive(Clone)]
+struct MoonlightRunner {
+    handshake: Arc<proto::MoonlightHandshake>,
+    config: Arc<RwLock<config::Config>>,
+    stats: debug::DebugStats,
+}
+
+/// A plain struct is already `(sss)`, so `Actions` returns `a(sss)` as KRunner expects
 #[derive(Serialize, Type, Clone, Debug)]
-#[zvariant(signature = "a(sss)")]
 struct Action {
     id: String,
     text: String,
@@ -32,25 +54,68 @@ struct Match {
     properties: MatchProperties,
 }
 
-impl Into<Match> for &proto::MoonlightResult {
-    fn into(self) -> Match {
-        Match {
-            id: self.id.clone(),
-            text: self.title.clone(),
-            relevance: self.score,
-            properties: MatchProperties {
-                subtext: self.subtitle.clone(),
-                category: Some("Discord".to_string()),
-            },
+impl MoonlightRunner {
+    async fn search(
+        &self,
+        config: &config::Config,
+        query: query::Query,
+    ) -> eyre::Result<Vec<Match>> {
+        let (request, limit) = match query {
+            query::Query::Switch(query) => (
+                proto::MoonlightRequest::Search {
+                    query,
+                    limit: config.results.max,
+                },
+                config.results.max,
+            ),
+            query::Query::Pages(text) => {
+                // The client gave us everything up front, no need to bother it
+                let results = pages::match_pages(&self.handshake.pages, &text);
+                return Ok(Self::to_matches(config, &results));
+            }
+            query::Query::FindMessages { text, page } => (
+                proto::MoonlightRequest::SearchMessages {
+                    query: text,
+                    limit: config.results.message_page_size,
+                    offset: (page - 1) * config.results.message_page_size,
+                },
+                config.results.message_page_size,
+            ),
+        };
 
-            icon: self
-                .icon
-                .clone()
-                .unwrap_or("com.discord.Discord".to_string()),
+        let results: Vec<proto::MoonlightResult> =
+            proto::request(&request, Duration::from_millis(config.timeouts.response)).await?;
 
-            category_relevance: self.category_relevance,
+        // Don't trust the client to respect the limit
+        let results = &results[..results.len().min(limit as usize)];
+        Ok(Self::to_matches(config, results))
+    }
+
+    fn debug_interface(&self) -> debug::DebugInterface {
+        debug::DebugInterface {
+            stats: self.stats.clone(),
         }
     }
+
+    fn to_matches(config: &config::Config, results: &[proto::MoonlightResult]) -> Vec<Match> {
+        results
+            .iter()
+            .map(|result| Match {
+                id: result.id.clone(),
+                text: result.title.clone(),
+                icon: result
+                    .icon
+                    .clone()
+                    .unwrap_or_else(|| config.default_icon(&result.r#type).to_string()),
+                category_relevance: result.category_relevance,
+                relevance: result.score * config.weight(&result.r#type),
+                properties: MatchProperties {
+                    subtext: result.subtitle.clone(),
+                    category: Some(config.category(&result.r#type).to_string()),
+                },
+            })
+            .collect()
+    }
 }
 
 // https://github.com/KDE/krunner/blob/19fed4f415f7eeeb75a5dd784ad4aebf88d53934/src/data/org.kde.krunner1.xml
@@ -63,26 +128,62 @@ impl MoonlightRunner {
         vec![Action {
             id: "open".to_string(),
             text: "Open".to_string(),
-            icon: "com.discord.Discord".to_string(),
+            icon: self.config.read().unwrap().icons.fallback.clone(),
         }]
     }
 
-    async fn _match(&self, query: &str) -> zbus::fdo::Result<Vec<Match>> {
-        proto::write_request(&proto::MoonlightRequest::Search {
-            query: query.to_string(),
-        })
-        .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
-        let results = proto::read_results()
-            .await
-            .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
-        Ok(results.iter().map(|p| p.into()).collect())
+    async fn _match(
+        &self,
+        query: &str,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<Vec<Match>> {
+        self.stats.record_client(&header);
+
+        let config = self.config.read().unwrap().clone();
+        let parsed = query::Query::parse(query, &config.triggers, &self.handshake.pages);
+        let kind = parsed.kind();
+
+        let start = Instant::now();
+        let result = self.search(&config, parsed).await;
+        let latency = start.elapsed();
+
+        self.stats
+            .record_query(query, kind, latency, result.as_ref().map(|x| x.len()));
+        match result {
+            Ok(matches) => {
+                debug!(
+                    query,
+                    kind,
+                    ?latency,
+                    results = matches.len(),
+                    "Query finished"
+                );
+                Ok(matches)
+            }
+            Err(e) => {
+                warn!(query, kind, ?latency, "Query failed: {e:#}");
+                Err(zbus::fdo::Error::Failed(e.to_string()))
+            }
+        }
     }
 
-    async fn run(&self, match_id: &str, _action_id: &str) -> zbus::fdo::Result<()> {
-        proto::write_request(&proto::MoonlightRequest::Run {
-            id: match_id.to_string(),
-        })
-        .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+    async fn run(
+        &self,
+        match_id: &str,
+        _action_id: &str,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.stats.record_client(&header);
+        debug!(match_id, "Running match");
+
+        let request = match match_id.strip_prefix(pages::PAGE_ID_PREFIX) {
+            Some(id) => proto::MoonlightRequest::OpenPage { id: id.to_string() },
+            None => proto::MoonlightRequest::Run {
+                id: match_id.to_string(),
+            },
+        };
+
+        proto::write_request(&request).map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
     }
 
     async fn config(&self) -> HashMap<String, OwnedValue> {
@@ -93,16 +194,105 @@ impl MoonlightRunner {
     async fn teardown(&self) {}
 }
 
+/// Moves the runner to a new service name and/or object path without dropping the connection
+async fn move_runner(
+    conn: &Connection,
+    runner: &MoonlightRunner,
+    old: &config::DBusConfig,
+    new: &config::DBusConfig,
+) -> eyre::Result<()> {
+    if new.object_path != old.object_path {
+        let object_server = conn.object_server();
+        object_server
+            .at(new.object_path.as_str(), runner.clone())
+            .await?;
+        object_server
+            .at(new.object_path.as_str(), runner.debug_interface())
+            .await?;
+        object_server
+            .remove::<MoonlightRunner, _>(old.object_path.as_str())
+            .await?;
+        object_server
+            .remove::<debug::DebugInterface, _>(old.object_path.as_str())
+            .await?;
+    }
+
+    if new.service_name != old.service_name {
+        conn.request_name(new.service_name.as_str()).await?;
+        conn.release_name(old.service_name.as_str()).await?;
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    let runner = MoonlightRunner;
-    let _conn = Builder::session()?
-        .name("com.notnite.moonlight-krunner")?
-        .serve_at("/moonlight_krunner", runner)?
+    let args = config::Args::parse();
+
+    // stdout is for talking to Discord, so logs go to stderr (which it shows in its console)
+    let filter = match &args.log {
+        Some(filter) => EnvFilter::try_new(filter)?,
+        None => EnvFilter::try_from_env("MOONLIGHT_KRUNNER_LOG")
+            .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER)),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(false)
+        .init();
+
+    if let Some(fixture) = &args.fake_client {
+        proto::use_fake_client(fake::FakeClient::load(fixture)?);
+        info!(fixture = %fixture.display(), "Using fake client");
+    }
+
+    let mut loader = config::ConfigLoader::new(args);
+    let config = loader.load()?;
+
+    // Ask the client for the things that only it knows about before we start taking queries
+    let handshake: proto::MoonlightHandshake =
+        proto::request(&proto::MoonlightRequest::Hello, HANDSHAKE_TIMEOUT).await?;
+    info!(pages = handshake.pages.len(), "Received handshake");
+
+    let mut dbus = config.dbus.clone();
+    let runner = MoonlightRunner {
+        handshake: Arc::new(handshake),
+        config: Arc::new(RwLock::new(config)),
+        stats: debug::DebugStats::default(),
+    };
+
+    let conn = Builder::session()?
+        .name(dbus.service_name.as_str())?
+        .serve_at(dbus.object_path.as_str(), runner.clone())?
+        .serve_at(dbus.object_path.as_str(), runner.debug_interface())?
         .build()
         .await?;
+    info!(
+        service_name = dbus.service_name,
+        object_path = dbus.object_path,
+        "Listening on D-Bus"
+    );
 
     loop {
         tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let config = match loader.reload_if_changed() {
+            Some(Ok(config)) => config,
+            Some(Err(e)) => {
+                error!("Failed to reload config: {e:#}");
+                continue;
+            }
+            None => continue,
+        };
+
+        if config.dbus != dbus {
+            if let Err(e) = move_runner(&conn, &runner, &dbus, &config.dbus).await {
+                error!("Failed to move runner: {e:#}");
+            }
+            dbus = config.dbus.clone();
+        }
+
+        info!("Reloaded config");
+        *runner.config.write().unwrap() = config;
     }
 }
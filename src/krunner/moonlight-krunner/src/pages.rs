@@ -0,0 +1,67 @@
+use crate::proto::{MoonlightPage, MoonlightResult, MoonlightResultType};
+
+/// Prefix used in match IDs so `Run` knows to open a page instead of a quick switcher result
+pub const PAGE_ID_PREFIX: &str = "page:";
+
+const MAX_PAGE_RESULTS: usize = 10;
+
+/// Scores how well the query describes a page, or `None` if some word doesn't fit at all.
+/// Every query word has to be the start of a word in the title or keywords, and shorter
+/// leftovers score higher (so "voice" prefers "Voice & Video" over "Voice Messages").
+fn score_page(page: &MoonlightPage, words: &[String]) -> Option<f64> {
+    let haystack = page
+        .title
+        .split(|c: char| !c.is_alphanumeric())
+        .chain(page.keywords.iter().map(|x| x.as_str()))
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<_>>();
+
+    let mut total = 0.;
+    for word in words {
+        let best = haystack
+            .iter()
+            .filter(|candidate| candidate.starts_with(word.as_str()))
+            .map(|candidate| word.len() as f64 / candidate.len() as f64)
+            .fold(None, |best: Option<f64>, score| {
+                Some(best.map_or(score, |best| best.max(score)))
+            })?;
+        total += best;
+    }
+
+    Some(total / words.len() as f64)
+}
+
+pub fn match_pages(pages: &[MoonlightPage], query: &str) -> Vec<MoonlightResult> {
+    let words = query
+        .split_whitespace()
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    let mut scored = pages
+        .iter()
+        .filter_map(|page| score_page(page, &words).map(|score| (page, score)))
+        .collect::<Vec<_>>();
+    scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    scored
+        .into_iter()
+        .take(MAX_PAGE_RESULTS)
+        .map(|(page, score)| MoonlightResult {
+            r#type: MoonlightResultType::Page,
+            id: format!("{PAGE_ID_PREFIX}{}", page.id),
+            title: page.title.clone(),
+            subtitle: page.subtitle.clone(),
+            icon: None,
+            score,
+            category_relevance: if page.title.eq_ignore_ascii_case(query.trim()) {
+                100
+            } else {
+                70
+            },
+        })
+        .collect()
+}
@@ -1,11 +1,39 @@
-use serde::{Deserialize, Serialize};
-use tokio::io::AsyncBufReadExt;
+use crate::fake::FakeClient;
+use eyre::OptionExt;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    collections::HashMap,
+    sync::{
+        LazyLock, Once, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    sync::oneshot,
+};
+use tracing::{debug, warn};
 
 #[derive(Serialize)]
 #[serde(tag = "type")]
 pub enum MoonlightRequest {
-    Search { query: String },
-    Run { id: String },
+    Hello,
+    Search {
+        query: String,
+        limit: u32,
+    },
+    SearchMessages {
+        query: String,
+        limit: u32,
+        offset: u32,
+    },
+    Run {
+        id: String,
+    },
+    OpenPage {
+        id: String,
+    },
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -15,6 +43,8 @@ pub enum MoonlightResultType {
     GroupDM,
     TextChannel,
     VoiceChannel,
+    Message,
+    Page,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -28,17 +58,162 @@ pub struct MoonlightResult {
     pub category_relevance: i32,
 }
 
+/// A built-in page or user settings section the client knows how to open
+#[derive(Deserialize, Clone, Debug)]
+pub struct MoonlightPage {
+    pub id: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+/// Sent by the client in response to [`MoonlightRequest::Hello`]
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct MoonlightHandshake {
+    #[serde(default)]
+    pub pages: Vec<MoonlightPage>,
+}
+
+static FAKE_CLIENT: OnceLock<FakeClient> = OnceLock::new();
+
+/// Answers every request with the fake client instead of talking over stdio
+pub fn use_fake_client(client: FakeClient) {
+    if FAKE_CLIENT.set(client).is_err() {
+        panic!("Fake client was already set");
+    }
+}
+
 pub fn write_request(request: &MoonlightRequest) -> eyre::Result<()> {
+    if let Some(fake) = FAKE_CLIENT.get() {
+        fake.respond(request);
+        return Ok(());
+    }
+
     println!("{}", serde_json::to_string(request)?);
     Ok(())
 }
 
-pub async fn read_results() -> eyre::Result<Vec<MoonlightResult>> {
-    let stdin = tokio::io::stdin();
-    let mut buf = tokio::io::BufReader::new(stdin);
+/// A request the client has to answer, with an ID for it to send back
+#[derive(Serialize)]
+struct IdentifiedRequest<'a> {
+    id: u64,
+    #[serde(flatten)]
+    request: &'a MoonlightRequest,
+}
+
+/// The client's answer to an [`IdentifiedRequest`]
+#[derive(Deserialize)]
+struct Response {
+    id: u64,
+    data: serde_json::Value,
+}
+
+#[derive(Debug)]
+pub struct TimedOut;
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timed out waiting for the client to respond")
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Where to send the response to each request we're waiting on, by ID.
+/// `None` once the client closed stdin, since nothing will answer after that
+type Pending = Option<HashMap<u64, oneshot::Sender<serde_json::Value>>>;
+
+static PENDING: LazyLock<std::sync::Mutex<Pending>> =
+    LazyLock::new(|| std::sync::Mutex::new(Some(HashMap::new())));
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static READER: Once = Once::new();
+
+/// Hands every response the client sends to the request waiting on it
+async fn read_responses() {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                warn!("Client closed stdin");
+                break;
+            }
+            Err(e) => {
+                warn!("Failed to read from the client: {e:#}");
+                break;
+            }
+        };
+
+        let response: Response = match serde_json::from_str(&line) {
+            Ok(response) => response,
+            Err(e) => {
+                warn!(
+                    line,
+                    "Skipping a line from the client that isn't a response: {e:#}"
+                );
+                continue;
+            }
+        };
+
+        let sender = PENDING
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|x| x.remove(&response.id));
+        match sender {
+            Some(sender) => _ = sender.send(response.data),
+            // Timed out already
+            None => debug!(id = response.id, "Dropping stale response"),
+        }
+    }
+
+    // Fails everything still waiting, and anything asked from now on
+    PENDING.lock().unwrap().take();
+}
+
+/// Sends a request and waits for the client to respond to it.
+/// Any number can be in flight at once, and late responses to ones we gave up on are dropped.
+pub async fn request<T: DeserializeOwned>(
+    request: &MoonlightRequest,
+    timeout: Duration,
+) -> eyre::Result<T> {
+    if let Some(fake) = FAKE_CLIENT.get() {
+        let response = fake
+            .respond(request)
+            .ok_or_eyre("Fake client doesn't respond to this request")?;
+        return Ok(serde_json::from_value(response)?);
+    }
+
+    READER.call_once(|| {
+        tokio::spawn(read_responses());
+    });
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = oneshot::channel();
+    PENDING
+        .lock()
+        .unwrap()
+        .as_mut()
+        .ok_or_eyre("Client closed stdin")?
+        .insert(id, sender);
 
-    let mut str = String::new();
-    buf.read_line(&mut str).await?;
+    let response = async {
+        println!(
+            "{}",
+            serde_json::to_string(&IdentifiedRequest { id, request })?
+        );
+        let data = receiver
+            .await
+            .map_err(|_| eyre::eyre!("Client closed stdin"))?;
+        eyre::Ok(serde_json::from_value(data)?)
+    };
+    let response = tokio::time::timeout(timeout, response).await;
 
-    Ok(serde_json::from_str(&str)?)
+    if response.is_err()
+        && let Some(pending) = PENDING.lock().unwrap().as_mut()
+    {
+        pending.remove(&id);
+    }
+    response.unwrap_or_else(|_| Err(TimedOut.into()))
 }
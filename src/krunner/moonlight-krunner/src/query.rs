@@ -0,0 +1,69 @@
+use crate::{config::TriggerConfig, pages, proto::MoonlightPage};
+
+#[derive(Debug, PartialEq)]
+pub enum Query {
+    /// Anything that isn't a command goes through the quick switcher
+    Switch(String),
+    /// `dc find <text> [page:N]`, with the trigger words from the config
+    FindMessages { text: String, page: u32 },
+    /// `dc <page>`, e.g. `dc settings voice` or `dc friends`, if some page matches
+    Pages(String),
+}
+
+impl Query {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Query::Switch(_) => "Switch",
+            Query::FindMessages { .. } => "FindMessages",
+            Query::Pages(_) => "Pages",
+        }
+    }
+
+    pub fn parse(query: &str, triggers: &TriggerConfig, pages: &[MoonlightPage]) -> Self {
+        let Some(command) = query
+            .trim_start()
+            .strip_prefix(triggers.prefix.as_str())
+            .and_then(|command| command.strip_prefix(' '))
+            .map(|command| command.trim())
+            .filter(|command| !command.is_empty())
+        else {
+            return Query::Switch(query.to_string());
+        };
+
+        let Some(text) = command
+            .strip_prefix(triggers.find.as_str())
+            .and_then(|text| text.strip_prefix(' '))
+            .map(|text| text.trim())
+            .filter(|text| !text.is_empty())
+        else {
+            // Channels and users can start with the trigger word too, e.g. "dc comics"
+            if pages::match_pages(pages, command).is_empty() {
+                return Query::Switch(query.to_string());
+            }
+            return Query::Pages(command.to_string());
+        };
+
+        // Discord search already uses `key:value` filters, so pages follow suit
+        let mut page = 1;
+        let mut words = Vec::new();
+        for word in text.split_whitespace() {
+            if let Some(n) = word
+                .strip_prefix("page:")
+                .and_then(|n| n.parse::<u32>().ok())
+            {
+                page = n.max(1);
+            } else {
+                words.push(word);
+            }
+        }
+
+        if words.is_empty() {
+            return Query::Switch(query.to_string());
+        }
+
+        Query::FindMessages {
+            text: words.join(" "),
+            page,
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Runs the real binary against a fake client on a private session bus, talking to it like KRunner would
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use zbus::{Connection, conn::Builder, fdo::DBusProxy, names::BusName, proxy};
+use zvariant::OwnedValue;
+
+// id, text, icon, category relevance, relevance, properties
+type Match = (
+    String,
+    String,
+    String,
+    i32,
+    f64,
+    HashMap<String, OwnedValue>,
+);
+// id, text, icon
+type Action = (String, String, String);
+
+#[proxy(interface = "org.kde.krunner1", default_path = "/moonlight_krunner")]
+trait Runner {
+    fn actions(&self) -> zbus::Result<Vec<Action>>;
+    #[zbus(name = "Match")]
+    fn match_(&self, query: &str) -> zbus::Result<Vec<Match>>;
+    fn run(&self, match_id: &str, action_id: &str) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "com.notnite.MoonlightKrunner.Debug",
+    default_path = "/moonlight_krunner"
+)]
+trait Debug {
+    fn recent_queries(&self) -> zbus::Result<Vec<(u64, String, String, u32, f64, String)>>;
+    #[zbus(property)]
+    fn query_count(&self) -> zbus::Result<u64>;
+}
+
+/// A `dbus-daemon --session` of our own, killed when dropped
+struct TestBus {
+    daemon: Child,
+    address: String,
+}
+
+impl TestBus {
+    fn start() -> Self {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("dbus-daemon is needed to run these tests");
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+
+        Self {
+            daemon,
+            address: address.trim().to_string(),
+        }
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/// The runner binary serving a fake client on a test bus
+struct TestRunner {
+    process: Child,
+    /// Everything it logged so far, one line per entry
+    logs: Arc<Mutex<Vec<String>>>,
+    connection: Connection,
+    service_name: String,
+    // Dropped last, so the runner is gone before its bus
+    _bus: TestBus,
+}
+
+impl TestRunner {
+    async fn start() -> Self {
+        Self::with_fixture("discord.json").await
+    }
+
+    /// Starts it with one of the fixtures in `tests/fixtures` instead
+    async fn with_fixture(name: &str) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let bus = TestBus::start();
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let service_name = format!("com.notnite.moonlight-krunner.test{id}");
+        let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        // Doesn't exist, so the defaults are used no matter what's in the user's config
+        let config = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("no-config.toml");
+
+        let mut process = Command::new(env!("CARGO_BIN_EXE_moonlight-krunner"))
+            .arg("--fake-client")
+            .arg(&fixture)
+            .arg("--config")
+            .arg(&config)
+            .args(["--service-name", &service_name])
+            .args(["--log", "info"])
+            .env("DBUS_SESSION_BUS_ADDRESS", &bus.address)
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // Passed through so they still show up when a test fails
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let stderr = BufReader::new(process.stderr.take().unwrap());
+        std::thread::spawn({
+            let logs = logs.clone();
+            move || {
+                for line in stderr.lines().map_while(Result::ok) {
+                    eprintln!("{line}");
+                    logs.lock().unwrap().push(line);
+                }
+            }
+        });
+
+        let connection = Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let runner = Self {
+            process,
+            logs,
+            connection,
+            service_name,
+            _bus: bus,
+        };
+        runner.wait_for_name().await;
+        runner
+    }
+
+    async fn wait_for_name(&self) {
+        let dbus = DBusProxy::new(&self.connection).await.unwrap();
+        let name = BusName::try_from(self.service_name.as_str()).unwrap();
+        for _ in 0..100 {
+            if dbus.name_has_owner(name.clone()).await.unwrap() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("Runner never showed up on the bus");
+    }
+
+    /// Waits for the runner to log a line containing `text`
+    async fn wait_for_log(&self, text: &str) {
+        for _ in 0..100 {
+            if self.logs.lock().unwrap().iter().any(|x| x.contains(text)) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("Runner never logged {text:?}");
+    }
+
+    async fn runner(&self) -> RunnerProxy<'_> {
+        RunnerProxy::builder(&self.connection)
+            .destination(self.service_name.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap()
+    }
+
+    async fn debug(&self) -> DebugProxy<'_> {
+        DebugProxy::builder(&self.connection)
+            .destination(self.service_name.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for TestRunner {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn string_property(properties: &HashMap<String, OwnedValue>, key: &str) -> Option<String> {
+    properties
+        .get(key)
+        .and_then(|x| String::try_from(x.try_clone().unwrap()).ok())
+}
+
+#[tokio::test]
+async fn actions_has_open() {
+    let test = TestRunner::start().await;
+
+    let actions = test.runner().await.actions().await.unwrap();
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].0, "open");
+}
+
+#[tokio::test]
+async fn match_finds_users_and_guilds() {
+    let test = TestRunner::start().await;
+
+    let matches = test.runner().await.match_("bob").await.unwrap();
+    let ids = matches.iter().map(|x| x.0.as_str()).collect::<Vec<_>>();
+    assert_eq!(ids, ["user:1", "user:3", "guild:11"]);
+
+    let (_, text, icon, category_relevance, relevance, properties) = &matches[0];
+    assert_eq!(text, "Bob");
+    assert_eq!(icon, "com.discord.Discord");
+    assert_eq!(*category_relevance, 100);
+    assert_eq!(*relevance, 1.);
+    assert_eq!(
+        string_property(properties, "subtext").as_deref(),
+        Some("bob")
+    );
+    assert_eq!(
+        string_property(properties, "category").as_deref(),
+        Some("Discord")
+    );
+}
+
+#[tokio::test]
+async fn match_respects_result_limit() {
+    // Sends all 8 of its users, so it's up to the runner to cut them down
+    let test = TestRunner::with_fixture("ignores-limit.json").await;
+
+    let matches = test.runner().await.match_("e").await.unwrap();
+    assert_eq!(matches.len(), 5);
+}
+
+#[tokio::test]
+async fn match_channels_have_guild_subtext() {
+    let test = TestRunner::start().await;
+
+    let matches = test.runner().await.match_("general").await.unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].0, "channel:100");
+    assert_eq!(
+        string_property(&matches[0].5, "subtext").as_deref(),
+        Some("moonlight")
+    );
+}
+
+#[tokio::test]
+async fn match_pages() {
+    let test = TestRunner::start().await;
+    let runner = test.runner().await;
+
+    let matches = runner.match_("dc settings voice").await.unwrap();
+    assert_eq!(matches.len(), 1);
+    let (id, text, icon, _, _, properties) = &matches[0];
+    assert_eq!(id, "page:settings:VOICE");
+    assert_eq!(text, "Voice & Video");
+    assert_eq!(icon, "preferences-system");
+    assert_eq!(
+        string_property(properties, "category").as_deref(),
+        Some("Discord Pages")
+    );
+
+    let matches = runner.match_("dc inbox").await.unwrap();
+    assert_eq!(matches[0].0, "page:route:NOTIFICATIONS");
+    assert_eq!(matches[0].3, 100);
+
+    let matches = runner.match_("dc settings").await.unwrap();
+    assert_eq!(matches.len(), 2);
+
+    assert!(runner.match_("dc nonsense").await.unwrap().is_empty());
+
+    // Not a page, so it goes to the quick switcher as is
+    let matches = runner.match_("dc comics").await.unwrap();
+    let ids = matches.iter().map(|x| x.0.as_str()).collect::<Vec<_>>();
+    assert_eq!(ids, ["guild:12"]);
+}
+
+#[tokio::test]
+async fn match_finds_messages() {
+    let test = TestRunner::start().await;
+    let runner = test.runner().await;
+
+    let matches = runner.match_("dc find config").await.unwrap();
+    let ids = matches.iter().map(|x| x.0.as_str()).collect::<Vec<_>>();
+    assert_eq!(ids, ["message:1000", "message:1001"]);
+    assert_eq!(
+        string_property(&matches[1].5, "subtext").as_deref(),
+        Some("alice in #general")
+    );
+    assert_eq!(
+        string_property(&matches[1].5, "category").as_deref(),
+        Some("Discord Messages")
+    );
+
+    // Only ten per page
+    assert!(
+        runner
+            .match_("dc find config page:2")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn run_accepts_results_and_pages() {
+    let test = TestRunner::start().await;
+    let runner = test.runner().await;
+
+    runner.run("user:1", "").await.unwrap();
+    test.wait_for_log(r#"Fake client asked to run result id="user:1""#)
+        .await;
+
+    // Pages go to the client without the prefix that keeps them apart from results
+    runner.run("page:settings:VOICE", "open").await.unwrap();
+    test.wait_for_log(r#"Fake client asked to open page id="settings:VOICE""#)
+        .await;
+}
+
+#[tokio::test]
+async fn debug_records_queries() {
+    let test = TestRunner::start().await;
+
+    test.runner().await.match_("bob").await.unwrap();
+    test.runner().await.match_("dc friends").await.unwrap();
+
+    let debug = test.debug().await;
+    assert_eq!(debug.query_count().await.unwrap(), 2);
+
+    let recent = debug.recent_queries().await.unwrap();
+    let queries = recent
+        .iter()
+        .map(|(_, query, kind, results, _, error)| {
+            (query.as_str(), kind.as_str(), *results, error.as_str())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        queries,
+        [("dc friends", "Pages", 1, ""), ("bob", "Switch", 3, "")]
+    );
+}
@@ -18,7 +18,8 @@ module.exports = {
     const nativePath = moonlightNode.getConfigOption<string>("krunner", "nativePath");
     if (nativePath == null) return;
 
-    pluginProcess = child_process.spawn(nativePath);
+    const nativeArgs = moonlightNode.getConfigOption<string[]>("krunner", "nativeArgs") ?? [];
+    pluginProcess = child_process.spawn(nativePath, nativeArgs);
 
     let readBuffer = Buffer.alloc(0);
     pluginProcess.stdout!.on("data", (data: Buffer) => {
@@ -42,7 +43,29 @@ module.exports = {
     });
 
     pluginProcess.stderr!.on("data", (data: Buffer) => {
-      logger.error(data.toString());
+      // The plugin logs one line per event, keep its level instead of treating everything as an error
+      for (const line of data.toString().split("\n")) {
+        if (line.trim() === "") continue;
+
+        const level = line.match(/^\S+\s+(TRACE|DEBUG|INFO|WARN|ERROR)\s/)?.[1];
+        switch (level) {
+          case "TRACE":
+            logger.trace(line);
+            break;
+          case "DEBUG":
+            logger.debug(line);
+            break;
+          case "INFO":
+            logger.info(line);
+            break;
+          case "WARN":
+            logger.warn(line);
+            break;
+          default:
+            logger.error(line);
+            break;
+        }
+      }
     });
 
     pluginProcess.on("exit", () => {
@@ -50,8 +73,12 @@ module.exports = {
     });
   },
 
-  sendResults(results) {
+  sendResults(requestId, results) {
     //logger.debug("Sending results:", results);
-    pluginProcess?.stdin?.write(`${JSON.stringify(results)}\n`);
+    pluginProcess?.stdin?.write(`${JSON.stringify({ id: requestId, data: results })}\n`);
+  },
+
+  sendHandshake(requestId, handshake) {
+    pluginProcess?.stdin?.write(`${JSON.stringify({ id: requestId, data: handshake })}\n`);
   }
 } satisfies KRunnerNatives;
@@ -1,15 +1,33 @@
 export type KRunnerRequest =
+  | {
+      type: "Hello";
+      // Sent back with the response, so the runner can drop ones it already gave up on
+      id: number;
+    }
   | {
       type: "Search";
+      id: number;
+      query: string;
+      limit: number;
+    }
+  | {
+      type: "SearchMessages";
+      id: number;
       query: string;
+      limit: number;
+      offset: number;
     }
   | {
       type: "Run";
       id: string;
+    }
+  | {
+      type: "OpenPage";
+      id: string;
     };
 
 export type KRunnerSearchResult = {
-  type: "User" | "Guild" | "GroupDM" | "TextChannel" | "VoiceChannel";
+  type: "User" | "Guild" | "GroupDM" | "TextChannel" | "VoiceChannel" | "Message" | "Page";
   id: string;
   title: string;
   subtitle?: string;
@@ -18,9 +36,21 @@ export type KRunnerSearchResult = {
   category_relevance: number;
 };
 
+export type KRunnerPage = {
+  id: string;
+  title: string;
+  subtitle?: string;
+  keywords: string[];
+};
+
+export type KRunnerHandshake = {
+  pages: KRunnerPage[];
+};
+
 export type KRunnerRequestCallback = (request: KRunnerRequest) => void;
 
 export interface KRunnerNatives {
   registerCallback(search: KRunnerRequestCallback): void;
-  sendResults(results: KRunnerSearchResult[]): void;
+  sendResults(requestId: number, results: KRunnerSearchResult[]): void;
+  sendHandshake(requestId: number, handshake: KRunnerHandshake): void;
 }
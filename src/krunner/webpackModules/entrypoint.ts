@@ -1,11 +1,20 @@
 import {
   AuthenticationStore,
+  ChannelStore,
   GuildStore,
   RelationshipStore,
+  SelectedChannelStore,
   SelectedGuildStore
 } from "@moonlight-mod/wp/common_stores";
 import spacepack from "@moonlight-mod/wp/spacepack_spacepack";
-import type { KRunnerNatives, KRunnerSearchResult } from "../types";
+import type { KRunnerHandshake, KRunnerNatives, KRunnerPage, KRunnerSearchResult } from "../types";
+
+const logger = moonlight.getLogger("krunner/entrypoint");
+
+const { Endpoints, Routes, UserSettingsSections } = spacepack.require("discord/Constants");
+const { HTTP } = spacepack.require("discord/utils/HTTPUtils");
+const { transitionTo } = spacepack.require("discord/utils/NavigationUtils");
+const UserSettingsModalActionCreators = spacepack.require("discord/actions/UserSettingsModalActionCreators").default;
 
 interface QuickSwitcherSearchResultBase {
   score: number;
@@ -64,6 +73,18 @@ type QuickSwitcherSearchResult = QuickSwitcherSearchResultBase &
 
 type QuickSwitcherSearchResultType = QuickSwitcherSearchResult["type"];
 
+interface SearchMessage {
+  id: string;
+  channel_id: string;
+  content: string;
+  author: {
+    id: string;
+    username: string;
+    global_name: string | null;
+    avatar: string | null;
+  };
+}
+
 interface QuickSwitcherSearchConfig {
   frecencyBoosters?: boolean;
   blacklist?: Set<string>;
@@ -89,7 +110,7 @@ const selectResult = spacepack.findFunctionByStrings(
   "navigationReplace:"
 ) as (result: QuickSwitcherSearchResult) => void;
 
-function search(input: string) {
+function search(input: string, limit: number) {
   return new Promise<QuickSwitcherSearchResult[]>((resolve) => {
     let results: QuickSwitcherSearchResult[] | null = null;
     let timeout: NodeJS.Timeout | undefined;
@@ -120,7 +141,7 @@ function search(input: string) {
         }
       },
       ["USER", "GUILD", "GROUP_DM", "TEXT_CHANNEL" /*"VOICE_CHANNEL"*/],
-      5,
+      limit,
       {
         frecencyBoosters: true,
         blacklist
@@ -131,27 +152,114 @@ function search(input: string) {
   });
 }
 
+async function searchMessages(query: string, limit: number, offset: number) {
+  // Searching every guild at once isn't a thing, so stick to wherever the user currently is
+  const guildId: string | null = SelectedGuildStore.getGuildId();
+  const channelId: string | null = SelectedChannelStore.getChannelId();
+
+  let url: string;
+  if (guildId) url = Endpoints.SEARCH_GUILD(guildId);
+  else if (channelId) url = Endpoints.SEARCH_CHANNEL(channelId);
+  else return [];
+
+  try {
+    const { body } = await HTTP.get({
+      url,
+      query: {
+        content: query,
+        // Discord caps this at 25
+        limit: Math.min(limit, 25),
+        offset
+      },
+      oldFormErrors: true
+    });
+
+    // Each hit is wrapped in an array, and the index might still be building (202 with no messages)
+    const messages: SearchMessage[][] = body?.messages ?? [];
+    return messages.map((hit) => hit[0]).filter((message) => message != null);
+  } catch (e) {
+    logger.error("Failed to search messages", e);
+    return [];
+  }
+}
+
+// Pages that aren't settings sections, keyed by their name in `Routes`
+const NAVIGATION_PAGES: { route: string; title: string; keywords: string[] }[] = [
+  { route: "FRIENDS", title: "Friends", keywords: ["home", "dms"] },
+  { route: "MESSAGE_REQUESTS", title: "Message Requests", keywords: ["dms"] },
+  { route: "NOTIFICATIONS", title: "Inbox", keywords: ["mentions", "notifications", "unreads"] },
+  { route: "GUILD_DISCOVERY", title: "Discover", keywords: ["servers", "explore"] },
+  { route: "APPLICATION_STORE", title: "Nitro", keywords: ["premium"] },
+  { route: "COLLECTIBLES_SHOP", title: "Shop", keywords: ["collectibles", "decorations"] }
+];
+
+// Settings "sections" that aren't actually something you can navigate to
+const HIDDEN_SETTINGS_SECTIONS = new Set(["DIVIDER", "HEADER", "CUSTOM", "LOGOUT"]);
+
+// Built on demand so it matches whatever this version of Discord has
+function getPages() {
+  const pages: KRunnerPage[] = [];
+
+  for (const page of NAVIGATION_PAGES) {
+    if (typeof Routes[page.route] !== "string") continue;
+    pages.push({
+      id: `route:${page.route}`,
+      title: page.title,
+      keywords: page.keywords
+    });
+  }
+
+  for (const [key, section] of Object.entries<string>(UserSettingsSections ?? {})) {
+    if (HIDDEN_SETTINGS_SECTIONS.has(key) || typeof section !== "string") continue;
+    pages.push({
+      id: `settings:${key}`,
+      title: section,
+      subtitle: "User Settings",
+      keywords: ["settings", ...key.toLowerCase().split("_")]
+    });
+  }
+
+  return pages;
+}
+
+function openPage(id: string) {
+  const [kind, key] = id.split(":");
+  switch (kind) {
+    case "route": {
+      if (typeof Routes[key] === "string") transitionTo(Routes[key]);
+      break;
+    }
+
+    case "settings": {
+      const section = UserSettingsSections?.[key];
+      if (section != null) UserSettingsModalActionCreators.open(section);
+      break;
+    }
+  }
+}
+
 const natives: KRunnerNatives = moonlight.getNatives("krunner");
 
-// Keep the last sent results around so we can use the record when invoked
+// Keep what each sent result does around so we can run it when invoked
 let lastResults: {
   nonce: string;
-  results: QuickSwitcherSearchResult[];
+  actions: (() => void)[];
 } | null = null;
 
-function handleResults(search: string, results: QuickSwitcherSearchResult[]) {
+function handleResults(requestId: number, search: string, results: QuickSwitcherSearchResult[], limit: number) {
   const nonce = Date.now().toString();
 
   const iconFormat = "png";
   const iconSize = 20;
 
   const mapped: KRunnerSearchResult[] = [];
+  const actions: (() => void)[] = [];
 
   //const maxScore = Math.max(...results.map((r) => r.score));
   const maxScore = 15000;
 
   for (let i = 0; i < results.length; i++) {
-    if (mapped.length >= 5) break;
+    if (mapped.length >= limit) break;
 
     const id = `${nonce}-${i}`;
     const result = results[i];
@@ -232,39 +340,108 @@ function handleResults(search: string, results: QuickSwitcherSearchResult[]) {
         break;
       }
     }
+
+    actions.push(() => selectResult(result));
   }
 
   lastResults = {
     nonce,
-    results
+    actions
   };
 
-  natives?.sendResults(mapped);
+  natives?.sendResults(requestId, mapped);
 }
 
-let nextSearch: NodeJS.Timeout | null = null;
-function searchWithDebounce(query: string) {
+function handleMessageResults(requestId: number, messages: SearchMessage[]) {
+  const nonce = Date.now().toString();
+
+  const iconSize = 20;
+  const maxSnippetLength = 100;
+
+  const mapped: KRunnerSearchResult[] = [];
+  const actions: (() => void)[] = [];
+
+  for (let i = 0; i < messages.length; i++) {
+    const message = messages[i];
+    const channel = ChannelStore.getChannel(message.channel_id);
+    const guildId: string | null = channel?.guild_id ?? null;
+
+    let snippet = message.content.replace(/\s+/g, " ").trim();
+    if (snippet === "") snippet = "(no text content)";
+    if (snippet.length > maxSnippetLength) snippet = `${snippet.substring(0, maxSnippetLength - 1)}…`;
+
+    const author = RelationshipStore.getNickname(message.author.id) ?? message.author.global_name;
+    const guild: GuildRecord | null = guildId ? GuildStore.getGuild(guildId) : null;
+    let subtitle = author ?? message.author.username;
+    if (channel?.name) subtitle += ` in #${channel.name}`;
+    if (guild) subtitle += ` (${guild.name})`;
+
+    mapped.push({
+      type: "Message",
+      id: `${nonce}-${i}`,
+      title: snippet,
+      subtitle,
+      icon: message.author.avatar
+        ? `https://cdn.discordapp.com/avatars/${message.author.id}/${message.author.avatar}.png?size=${iconSize}`
+        : undefined,
+      // Discord already sorts these by relevance
+      score: 1 - i / messages.length,
+      category_relevance: 70
+    });
+    actions.push(() => transitionTo(Routes.CHANNEL(guildId ?? "@me", message.channel_id, message.id)));
+  }
+
+  lastResults = {
+    nonce,
+    actions
+  };
+
+  natives?.sendResults(requestId, mapped);
+}
+
+let nextSearch: { timeout: NodeJS.Timeout; requestId: number } | null = null;
+function withDebounce(requestId: number, run: () => void) {
   if (nextSearch) {
-    clearInterval(nextSearch);
-    nextSearch = null;
+    clearTimeout(nextSearch.timeout);
 
-    // lol
-    natives?.sendResults([]);
+    // Still answer the one we're skipping, the runner drops it if it stopped waiting
+    natives?.sendResults(nextSearch.requestId, []);
+    nextSearch = null;
   }
 
-  nextSearch = setTimeout(
+  const timeout = setTimeout(
     () => {
       nextSearch = null;
-      search(query).then((results) => handleResults(query, results));
+      run();
     },
     moonlight.getConfigOption<number>("krunner", "resolverDebounce")
   );
+  nextSearch = { timeout, requestId };
 }
 
 natives?.registerCallback((req) => {
   switch (req.type) {
+    case "Hello": {
+      const handshake: KRunnerHandshake = {
+        pages: getPages()
+      };
+      natives.sendHandshake(req.id, handshake);
+      break;
+    }
+
     case "Search": {
-      searchWithDebounce(req.query);
+      withDebounce(req.id, () =>
+        search(req.query, req.limit).then((results) => handleResults(req.id, req.query, results, req.limit))
+      );
+      break;
+    }
+
+    case "SearchMessages": {
+      withDebounce(req.id, () =>
+        searchMessages(req.query, req.limit, req.offset).then((messages) =>
+          handleMessageResults(req.id, messages.slice(0, req.limit))
+        )
+      );
       break;
     }
 
@@ -275,13 +452,17 @@ natives?.registerCallback((req) => {
       if (lastResults.nonce !== nonce) break;
 
       const idx = parseInt(idxStr, 10);
-      if (Number.isNaN(idx) || idx < 0 || idx >= lastResults.results.length) break;
+      if (Number.isNaN(idx) || idx < 0 || idx >= lastResults.actions.length) break;
 
-      const result = lastResults.results[idx];
-      selectResult(result);
+      lastResults.actions[idx]();
 
       lastResults = null;
       break;
     }
+
+    case "OpenPage": {
+      openPage(req.id);
+      break;
+    }
   }
 });
@@ -0,0 +1,342 @@
+use lofty::{config::ParseOptions, file::FileType, picture::PictureType, prelude::*, probe::Probe};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+// Anything bigger is probably a downloads folder and not worth reading every file of
+const MAX_ALBUM_FILES: usize = 300;
+const MAX_CACHED_DIRECTORIES: usize = 16;
+// In order of preference, matched ignoring case
+const FOLDER_ART_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+const FOLDER_ART_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// The tags we care about from one file
+#[derive(Clone, Debug, Default)]
+struct FileTags {
+    path: PathBuf,
+    album: String,
+    artist: String,
+    album_artist: String,
+    track: u32,
+    track_total: u32,
+    disc: u32,
+    disc_total: u32,
+}
+
+/// What the files next to a track say about its album, for what MPRIS doesn't tell us
+#[derive(Clone, Debug)]
+pub struct AlbumInfo {
+    /// On the same disc as the track
+    pub total_tracks: i32,
+    pub total_discs: i32,
+    /// Empty unless some file has it, or every track is by the same artist
+    pub album_artist: String,
+}
+
+fn read_tags(path: &Path) -> Option<FileTags> {
+    let tagged_file = Probe::open(path)
+        .ok()?
+        .options(
+            ParseOptions::new()
+                .read_properties(false)
+                .read_cover_art(false),
+        )
+        .read()
+        .ok()?;
+    let tag = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())?;
+
+    Some(FileTags {
+        path: path.to_path_buf(),
+        album: tag.album().unwrap_or_default().to_string(),
+        artist: tag.artist().unwrap_or_default().to_string(),
+        album_artist: tag
+            .get_string(ItemKey::AlbumArtist)
+            .unwrap_or_default()
+            .to_string(),
+        track: tag.track().unwrap_or_default(),
+        track_total: tag.track_total().unwrap_or_default(),
+        disc: tag.disk().unwrap_or_default(),
+        disc_total: tag.disk_total().unwrap_or_default(),
+    })
+}
+
+/// The picture embedded in a track, preferring the front cover if it has several
+pub fn embedded_art(path: &Path) -> Option<Vec<u8>> {
+    let tagged_file = Probe::open(path)
+        .ok()?
+        .options(ParseOptions::new().read_properties(false))
+        .read()
+        .ok()?;
+
+    // Lofty turns APIC frames, FLAC PICTURE blocks, MP4 covr atoms and
+    // METADATA_BLOCK_PICTURE comments into the same thing
+    let pictures = tagged_file
+        .tags()
+        .iter()
+        .flat_map(|x| x.pictures())
+        .collect::<Vec<_>>();
+    let picture = pictures
+        .iter()
+        .find(|x| x.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())?;
+
+    Some(picture.data().to_vec())
+}
+
+/// An image like `cover.jpg` or `folder.png` next to a track
+pub fn folder_art(path: &Path) -> Option<Vec<u8>> {
+    let images = std::fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|x| x.path())
+        .filter_map(|x| {
+            let name = x.file_stem()?.to_str()?.to_lowercase();
+            let extension = x.extension()?.to_str()?.to_lowercase();
+            let rank = FOLDER_ART_NAMES.iter().position(|x| *x == name)?;
+            FOLDER_ART_EXTENSIONS
+                .contains(&extension.as_str())
+                .then_some((rank, x))
+        })
+        .collect::<Vec<_>>();
+
+    let (_, image) = images.iter().min_by_key(|(rank, _)| *rank)?;
+    std::fs::read(image).ok()
+}
+
+fn scan_directory(dir: &Path) -> Vec<FileTags> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let files = entries
+        .filter_map(Result::ok)
+        .map(|x| x.path())
+        .filter(|x| FileType::from_path(x).is_some())
+        .take(MAX_ALBUM_FILES + 1)
+        .collect::<Vec<_>>();
+    if files.len() > MAX_ALBUM_FILES {
+        return Vec::new();
+    }
+
+    files.iter().filter_map(|x| read_tags(x)).collect()
+}
+
+/// Reads the tags of every audio file in a track's directory, remembering them per directory
+/// so we don't go through the whole album again on every update
+#[derive(Default)]
+pub struct AlbumScanner {
+    /// Most recently used first
+    directories: Mutex<VecDeque<(PathBuf, Vec<FileTags>)>>,
+}
+
+impl AlbumScanner {
+    async fn get_directory(&self, dir: &Path) -> Vec<FileTags> {
+        {
+            let mut directories = self.directories.lock().unwrap();
+            if let Some(idx) = directories.iter().position(|(path, _)| path == dir)
+                && let Some(entry) = directories.remove(idx)
+            {
+                let files = entry.1.clone();
+                directories.push_front(entry);
+                return files;
+            }
+        }
+
+        // Reading a whole album takes a while, and shouldn't hold up everything else on this thread
+        let owned_dir = dir.to_path_buf();
+        let files = tokio::task::spawn_blocking(move || scan_directory(&owned_dir))
+            .await
+            .unwrap_or_default();
+
+        let mut directories = self.directories.lock().unwrap();
+        directories.retain(|(path, _)| path != dir);
+        directories.push_front((dir.to_path_buf(), files.clone()));
+        directories.truncate(MAX_CACHED_DIRECTORIES);
+        files
+    }
+
+    /// `None` if the track couldn't be read or doesn't say which album it's from
+    pub async fn get_album(&self, path: &Path) -> Option<AlbumInfo> {
+        let files = self.get_directory(path.parent()?).await;
+        album_info(&files, path)
+    }
+}
+
+/// Puts together what the files in a directory say about the album `path` is on
+fn album_info(files: &[FileTags], path: &Path) -> Option<AlbumInfo> {
+    let track = files.iter().find(|x| x.path == path)?;
+    if track.album.is_empty() {
+        return None;
+    }
+
+    // A folder can have more than one album in it, only count the ones like this track
+    let album = files
+        .iter()
+        .filter(|x| x.album.eq_ignore_ascii_case(&track.album))
+        .collect::<Vec<_>>();
+
+    let disc = album
+        .iter()
+        .filter(|x| x.disc == track.disc)
+        .collect::<Vec<_>>();
+    let total_tracks = disc
+        .iter()
+        .map(|x| x.track_total.max(x.track))
+        .max()
+        .unwrap_or_default()
+        .max(disc.len() as u32);
+    let total_discs = album
+        .iter()
+        .map(|x| x.disc_total.max(x.disc))
+        .max()
+        .unwrap_or_default();
+
+    let album_artist = match album.iter().find(|x| !x.album_artist.is_empty()) {
+        Some(file) => file.album_artist.clone(),
+        None if album.iter().all(|x| x.artist == album[0].artist) => album[0].artist.clone(),
+        None => String::new(),
+    };
+
+    Some(AlbumInfo {
+        total_tracks: total_tracks as i32,
+        total_discs: total_discs as i32,
+        album_artist,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(name: &str, album: &str, artist: &str, track: u32, disc: u32) -> FileTags {
+        FileTags {
+            path: PathBuf::from("/music").join(name),
+            album: album.to_string(),
+            artist: artist.to_string(),
+            track,
+            disc,
+            ..Default::default()
+        }
+    }
+
+    /// Just enough of a FLAC file for lofty to read its Vorbis comments
+    fn flac(comments: &[&str]) -> Vec<u8> {
+        let mut data = b"fLaC".to_vec();
+
+        // STREAMINFO: 4096 sample blocks, 44.1kHz, stereo, 16 bit, length unknown
+        data.extend([0, 0, 0, 34]);
+        data.extend([0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+        data.extend([0x0a, 0xc4, 0x42, 0xf0, 0, 0, 0, 0]);
+        data.extend([0; 16]);
+
+        let mut block = Vec::new();
+        block.extend(0u32.to_le_bytes());
+        block.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            block.extend((comment.len() as u32).to_le_bytes());
+            block.extend(comment.as_bytes());
+        }
+        // Last block, VORBIS_COMMENT
+        data.push(0x84);
+        data.extend(&(block.len() as u32).to_be_bytes()[1..]);
+        data.extend(block);
+        data
+    }
+
+    #[test]
+    fn counts_tracks_on_the_same_disc() {
+        let files = [
+            tags("1.flac", "Album", "A", 1, 1),
+            tags("2.flac", "Album", "A", 2, 1),
+            tags("3.flac", "Album", "A", 1, 2),
+        ];
+
+        let album = album_info(&files, Path::new("/music/1.flac")).unwrap();
+        assert_eq!(album.total_tracks, 2);
+        assert_eq!(album.total_discs, 2);
+        assert_eq!(album.album_artist, "A");
+    }
+
+    #[test]
+    fn trusts_tagged_totals_over_files_present() {
+        let mut track = tags("1.flac", "Album", "A", 1, 1);
+        track.track_total = 12;
+        track.disc_total = 3;
+        let files = [track, tags("5.flac", "Album", "A", 5, 1)];
+
+        let album = album_info(&files, Path::new("/music/5.flac")).unwrap();
+        assert_eq!(album.total_tracks, 12);
+        assert_eq!(album.total_discs, 3);
+    }
+
+    #[test]
+    fn ignores_other_albums_in_the_folder() {
+        let files = [
+            tags("1.flac", "Album", "A", 1, 0),
+            tags("2.flac", "album", "A", 2, 0),
+            tags("3.flac", "Other", "B", 3, 0),
+        ];
+
+        let album = album_info(&files, Path::new("/music/1.flac")).unwrap();
+        assert_eq!(album.total_tracks, 2);
+        assert_eq!(album.total_discs, 0);
+    }
+
+    #[test]
+    fn album_artist() {
+        let mut tagged = tags("2.flac", "Album", "B", 2, 0);
+        tagged.album_artist = "Various Artists".to_string();
+        let files = [tags("1.flac", "Album", "A", 1, 0), tagged];
+        let album = album_info(&files, Path::new("/music/1.flac")).unwrap();
+        assert_eq!(album.album_artist, "Various Artists");
+
+        let files = [
+            tags("1.flac", "Album", "A", 1, 0),
+            tags("2.flac", "Album", "B", 2, 0),
+        ];
+        let album = album_info(&files, Path::new("/music/1.flac")).unwrap();
+        assert_eq!(album.album_artist, "");
+    }
+
+    #[test]
+    fn needs_an_album() {
+        let files = [tags("1.flac", "", "A", 1, 0), tags("2.flac", "", "A", 2, 0)];
+        assert!(album_info(&files, Path::new("/music/1.flac")).is_none());
+        assert!(album_info(&files, Path::new("/music/3.flac")).is_none());
+    }
+
+    #[tokio::test]
+    async fn get_album_reads_the_directory() {
+        let dir = std::env::temp_dir().join(format!("media-fetcher-album-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, comments) in [
+            ("1.flac", ["ALBUM=Album", "ARTIST=A", "TRACKNUMBER=1"]),
+            ("2.flac", ["ALBUM=Album", "ARTIST=A", "TRACKNUMBER=2"]),
+            ("3.flac", ["ALBUM=Album", "ARTIST=A", "TRACKNUMBER=3"]),
+        ] {
+            std::fs::write(dir.join(name), flac(&comments)).unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "not a track").unwrap();
+
+        let scanner = AlbumScanner::default();
+        let album = scanner.get_album(&dir.join("2.flac")).await;
+
+        // Cached, so a track added since isn't seen
+        std::fs::write(
+            dir.join("4.flac"),
+            flac(&["ALBUM=Album", "ARTIST=A", "TRACKNUMBER=4"]),
+        )
+        .unwrap();
+        let cached = scanner.get_album(&dir.join("2.flac")).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let album = album.unwrap();
+        assert_eq!(album.total_tracks, 3);
+        assert_eq!(album.album_artist, "A");
+        assert_eq!(cached.unwrap().total_tracks, 3);
+    }
+}
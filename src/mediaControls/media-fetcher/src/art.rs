@@ -0,0 +1,601 @@
+use crate::{
+    palette,
+    proto::{ArtCacheInfo, ArtFormat, ArtImage, ArtOptions, Palette, PlaybackStatus},
+};
+use base64::Engine;
+use image::{DynamicImage, ImageFormat, codecs::jpeg::JpegEncoder, imageops::FilterType::Triangle};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+// A few sizes of the last few covers
+const MAX_CACHED_ART: usize = 16;
+// Palettes and placeholders are tiny, so these can cover a lot more covers
+const MAX_CACHED_DETAILS: usize = 64;
+// Bump whenever what's made from a cover changes, like how it's cropped or its palette,
+// so nothing cached on disk from before is used
+const ART_VERSION: u32 = 1;
+// Written to the disk cache next to the images, anything not named like either is left alone
+const DETAILS_SUFFIX: &str = "-details.json";
+// Oldest files go first once the disk cache gets bigger than this
+const MAX_DISK_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+// How many colors the placeholder blends across and down, it's meant to be a blur anyway
+const PLACEHOLDER_COMPONENTS: u32 = 4;
+// Plenty for that few colors, and quick to go through
+const PLACEHOLDER_SAMPLE_SIZE: u32 = 32;
+
+/// Everything worked out from a cover besides its images
+#[derive(Serialize, Deserialize, Clone)]
+struct CoverDetails {
+    palette: Palette,
+    /// A BlurHash, see `PlaybackStatus::art_placeholder`
+    placeholder: String,
+}
+
+impl CoverDetails {
+    fn new(image: &DynamicImage) -> anyhow::Result<Self> {
+        let sample = image
+            .thumbnail(PLACEHOLDER_SAMPLE_SIZE, PLACEHOLDER_SAMPLE_SIZE)
+            .to_rgba8();
+        Ok(Self {
+            palette: palette::extract(image),
+            placeholder: blurhash::encode(
+                PLACEHOLDER_COMPONENTS,
+                PLACEHOLDER_COMPONENTS,
+                sample.width(),
+                sample.height(),
+                sample.as_raw(),
+            )?,
+        })
+    }
+}
+
+/// Decodes art in whatever format it came in. It can come from anywhere, like a download,
+/// so this refuses images that would take more memory than a cover ever needs.
+/// MediaRemote only gives out decoded images, so macOS never needs it
+#[cfg(not(target_os = "macos"))]
+pub fn decode(data: &[u8]) -> anyhow::Result<DynamicImage> {
+    // Bigger than any real cover
+    const MAX_DECODE_SIZE: u32 = 8192;
+    const MAX_DECODE_ALLOC: u64 = 300 * 1024 * 1024;
+
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_DECODE_SIZE);
+    limits.max_image_height = Some(MAX_DECODE_SIZE);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+
+    let mut reader = image::ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    reader.limits(limits);
+    Ok(reader.decode()?)
+}
+
+fn extension(format: ArtFormat) -> &'static str {
+    match format {
+        ArtFormat::Png => "png",
+        ArtFormat::Jpeg => "jpg",
+        ArtFormat::Webp => "webp",
+    }
+}
+
+/// Shrinks art to fit in `options.max_size` and encodes it
+fn encode(image: &DynamicImage, options: &ArtOptions) -> anyhow::Result<Vec<u8>> {
+    let size = options.max_size.max(1);
+    let image = if image.width() > size || image.height() > size {
+        // Keeps the aspect ratio
+        image.resize(size, size, Triangle)
+    } else {
+        image.clone()
+    };
+
+    let mut bytes = Vec::new();
+    match options.format {
+        ArtFormat::Png => image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?,
+        // Neither of these can take every pixel format PNG can
+        ArtFormat::Jpeg => JpegEncoder::new_with_quality(&mut bytes, options.quality.clamp(1, 100))
+            .encode_image(&image.to_rgb8())?,
+        ArtFormat::Webp => image
+            .to_rgba8()
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::WebP)?,
+    }
+    Ok(bytes)
+}
+
+fn mime_type(format: ArtFormat) -> &'static str {
+    match format {
+        ArtFormat::Png => "image/png",
+        ArtFormat::Jpeg => "image/jpeg",
+        ArtFormat::Webp => "image/webp",
+    }
+}
+
+/// For serving files from the cache, going by the extension `file_name` gave them
+pub fn content_type(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, x)| x) {
+        Some("jpg") => mime_type(ArtFormat::Jpeg),
+        Some("webp") => mime_type(ArtFormat::Webp),
+        _ => mime_type(ArtFormat::Png),
+    }
+}
+
+fn data_url(bytes: &[u8], format: ArtFormat) -> String {
+    let base64 = base64::prelude::BASE64_STANDARD.encode(bytes);
+    format!("data:{};base64,{}", mime_type(format), base64)
+}
+
+fn is_cache_key(key: &str) -> bool {
+    key.len() == 16 && key.bytes().all(|x| matches!(x, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Whether `name` is something `file_name` could have made, which is all `get_file` will read
+fn is_art_file(name: &str) -> bool {
+    let Some((key, rest)) = name.split_once('-') else {
+        return false;
+    };
+    let Some((size, extension)) = rest.split_once('.') else {
+        return false;
+    };
+    let is_number = |x: &str| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit());
+
+    is_cache_key(key)
+        && match extension {
+            "jpg" => size
+                .split_once("-q")
+                .is_some_and(|(size, quality)| is_number(size) && is_number(quality)),
+            "png" | "webp" => is_number(size),
+            _ => false,
+        }
+}
+
+/// Whether `name` is something we wrote to the disk cache, so nothing else in there gets touched
+fn is_cache_file(name: &str) -> bool {
+    is_art_file(name) || name.strip_suffix(DETAILS_SUFFIX).is_some_and(is_cache_key)
+}
+
+/// Names, modification times and sizes of everything we wrote to `directory`
+fn cache_files(directory: &Path) -> Vec<(PathBuf, SystemTime, u64)> {
+    let Ok(files) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    files
+        .filter_map(Result::ok)
+        .filter(|x| x.file_name().to_str().is_some_and(is_cache_file))
+        .filter_map(|x| {
+            let metadata = x.metadata().ok()?;
+            Some((x.path(), metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+}
+
+/// Deletes the oldest files we wrote to `directory` until they fit in `max_size` again
+fn evict(directory: &Path, max_size: u64) {
+    let mut files = cache_files(directory);
+    let mut size = files.iter().map(|(_, _, len)| len).sum::<u64>();
+    if size <= max_size {
+        return;
+    }
+
+    files.sort_by_key(|(_, modified, _)| *modified);
+    for (path, _, len) in files {
+        if size <= max_size {
+            break;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => size -= len,
+            Err(e) => eprintln!("Failed to remove {}: {:?}", path.display(), e),
+        }
+    }
+}
+
+/// Calls `load` the first time only, so a cover is decoded once however much is made from it
+fn decoded<'a>(
+    image: &'a mut Option<DynamicImage>,
+    load: &mut Option<impl FnOnce() -> anyhow::Result<DynamicImage>>,
+) -> anyhow::Result<&'a DynamicImage> {
+    if image.is_none()
+        && let Some(load) = load.take()
+    {
+        *image = Some(load()?);
+    }
+    Ok(image.as_ref().unwrap())
+}
+
+/// What one size of a cover is stored as, in memory and on disk
+fn file_name(key: &str, options: &ArtOptions) -> String {
+    match options.format {
+        ArtFormat::Jpeg => format!("{key}-{}-q{}.jpg", options.max_size, options.quality),
+        format => format!("{key}-{}.{}", options.max_size, extension(format)),
+    }
+}
+
+/// Turns anything identifying a piece of art, like its path and modification time, into a cache key.
+/// `DefaultHasher` can change between Rust versions, which only means the disk cache starts over
+pub fn cache_key(source: impl Hash) -> String {
+    let mut hasher = DefaultHasher::new();
+    ART_VERSION.hash(&mut hasher);
+    source.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// For `PlaybackStatus::track_key`, everything the platforms agree identifies a track
+pub fn track_key(status: &PlaybackStatus) -> String {
+    if status.title.is_empty() && status.artist.is_empty() {
+        return String::new();
+    }
+
+    cache_key((
+        &status.player_name,
+        &status.track_id,
+        &status.title,
+        &status.artist,
+        &status.album,
+    ))
+}
+
+/// Remembers encoded art so asking for the same cover again doesn't decode and resize it again,
+/// optionally keeping it on disk too so it survives restarts
+pub struct ArtCache {
+    /// File names and encoded images, most recently used first
+    entries: Mutex<VecDeque<(String, Arc<Vec<u8>>)>>,
+    directory: Option<PathBuf>,
+    /// From the last `Request::GetAlbumArt` that had any, for the art sent when the track changes
+    sizes: Mutex<Vec<ArtOptions>>,
+    /// Base URL of the art server, if it's running. Art is linked there instead of sent inline
+    server: Mutex<Option<String>>,
+    /// File names of the art sent last, in the order of `sizes`
+    current: Mutex<Vec<String>>,
+    /// Cache keys and what was worked out from their covers, most recently used first
+    details: Mutex<VecDeque<(String, CoverDetails)>>,
+    /// The last cover decoded for its placeholder, so sending its art right after doesn't decode it again
+    decoded: Mutex<Option<(String, DynamicImage)>>,
+}
+
+impl ArtCache {
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        Self {
+            entries: Default::default(),
+            directory,
+            sizes: Mutex::new(vec![ArtOptions::default()]),
+            server: Default::default(),
+            current: Default::default(),
+            details: Default::default(),
+            decoded: Default::default(),
+        }
+    }
+
+    pub fn set_server(&self, url: String) {
+        *self.server.lock().unwrap() = Some(url);
+    }
+
+    /// File name of the art sent last, in the size at `index` of what was asked for
+    pub fn current(&self, index: usize) -> Option<String> {
+        self.current.lock().unwrap().get(index).cloned()
+    }
+
+    pub fn sizes(&self) -> Vec<ArtOptions> {
+        self.sizes.lock().unwrap().clone()
+    }
+
+    /// Keeps the current sizes if `sizes` is empty
+    pub fn set_sizes(&self, sizes: Vec<ArtOptions>) {
+        if !sizes.is_empty() {
+            *self.sizes.lock().unwrap() = sizes;
+        }
+    }
+
+    fn remember(&self, name: &str, bytes: Arc<Vec<u8>>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(x, _)| x != name);
+        entries.push_front((name.to_string(), bytes));
+        entries.truncate(MAX_CACHED_ART);
+    }
+
+    /// One size of a cover by the name `file_name` gave it, from memory or disk
+    pub fn get_file(&self, name: &str) -> Option<Arc<Vec<u8>>> {
+        let cached = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, bytes)| bytes.clone());
+
+        let bytes = cached.or_else(|| {
+            // Names come from the server too, so only ones we could have made are looked up.
+            // Anything else could point outside the cache, like `C:x.png` on Windows
+            if !is_art_file(name) {
+                return None;
+            }
+            Some(Arc::new(
+                std::fs::read(self.directory.as_ref()?.join(name)).ok()?,
+            ))
+        })?;
+
+        self.remember(name, bytes.clone());
+        Some(bytes)
+    }
+
+    fn save(&self, name: &str, bytes: &[u8]) {
+        if let Some(directory) = &self.directory
+            && let Err(e) = std::fs::create_dir_all(directory)
+                .and_then(|_| std::fs::write(directory.join(name), bytes))
+        {
+            eprintln!(
+                "Failed to save album art to {}: {:?}",
+                directory.display(),
+                e
+            );
+        }
+    }
+
+    fn get_details(&self, key: &str) -> Option<CoverDetails> {
+        let cached = self
+            .details
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(x, _)| x == key)
+            .map(|(_, details)| details.clone());
+
+        let details = cached.or_else(|| {
+            let path = self
+                .directory
+                .as_ref()?
+                .join(format!("{key}{DETAILS_SUFFIX}"));
+            serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+        })?;
+
+        self.remember_details(key, details.clone());
+        Some(details)
+    }
+
+    fn remember_details(&self, key: &str, details: CoverDetails) {
+        let mut cached = self.details.lock().unwrap();
+        cached.retain(|(x, _)| x != key);
+        cached.push_front((key.to_string(), details));
+        cached.truncate(MAX_CACHED_DETAILS);
+    }
+
+    fn insert_details(&self, key: &str, image: &DynamicImage) -> anyhow::Result<CoverDetails> {
+        let details = CoverDetails::new(image)?;
+        self.save(
+            &format!("{key}{DETAILS_SUFFIX}"),
+            &serde_json::to_vec(&details)?,
+        );
+        self.remember_details(key, details.clone());
+        Ok(details)
+    }
+
+    /// The placeholder for the art under `key` if it's been worked out before, without loading anything
+    pub fn cached_placeholder(&self, key: &str) -> Option<String> {
+        self.get_details(key).map(|x| x.placeholder)
+    }
+
+    /// The placeholder for the art under `key`, calling `load` if it isn't cached, or `None` if that failed.
+    /// What `load` gave is kept for the next `get_or_insert` with the same key
+    pub fn placeholder(
+        &self,
+        key: &str,
+        load: impl FnOnce() -> anyhow::Result<DynamicImage>,
+    ) -> Option<String> {
+        if let Some(placeholder) = self.cached_placeholder(key) {
+            return Some(placeholder);
+        }
+
+        let result = load().and_then(|image| {
+            let details = self.insert_details(key, &image)?;
+            *self.decoded.lock().unwrap() = Some((key.to_string(), image));
+            Ok(details.placeholder)
+        });
+        result
+            .inspect_err(|e| eprintln!("Error making album art placeholder: {:?}", e))
+            .ok()
+    }
+
+    /// Returns the art under `key` in every size of `sizes` and its colors,
+    /// calling `load` once if any of them aren't cached
+    pub fn get_or_insert(
+        &self,
+        key: &str,
+        sizes: &[ArtOptions],
+        load: impl FnOnce() -> anyhow::Result<DynamicImage>,
+    ) -> anyhow::Result<(Vec<ArtImage>, Palette)> {
+        let mut image = self
+            .decoded
+            .lock()
+            .unwrap()
+            .take()
+            .filter(|(x, _)| x == key)
+            .map(|(_, image)| image);
+        let mut load = Some(load);
+        let mut images = Vec::new();
+        let mut names = Vec::new();
+        let mut saved = false;
+        let server = self.server.lock().unwrap().clone();
+
+        for options in sizes {
+            let name = file_name(key, options);
+            let bytes = match self.get_file(&name) {
+                Some(bytes) => bytes,
+                None => {
+                    let bytes = encode(decoded(&mut image, &mut load)?, options)?;
+                    self.save(&name, &bytes);
+                    saved = true;
+                    let bytes = Arc::new(bytes);
+                    self.remember(&name, bytes.clone());
+                    bytes
+                }
+            };
+
+            images.push(ArtImage {
+                options: *options,
+                data: match &server {
+                    Some(server) => format!("{server}/art/{name}"),
+                    None => data_url(&bytes, options.format),
+                },
+            });
+            names.push(name);
+        }
+
+        let details = match self.get_details(key) {
+            Some(details) => details,
+            None => {
+                saved = true;
+                self.insert_details(key, decoded(&mut image, &mut load)?)?
+            }
+        };
+
+        // Once for everything saved above, since it looks at every file in the folder
+        if saved && let Some(directory) = &self.directory {
+            evict(directory, MAX_DISK_CACHE_SIZE);
+        }
+
+        *self.current.lock().unwrap() = names;
+        Ok((images, details.palette))
+    }
+
+    pub fn info(&self) -> ArtCacheInfo {
+        let entries = self.entries.lock().unwrap();
+        let disk_files = self
+            .directory
+            .as_deref()
+            .map(cache_files)
+            .unwrap_or_default();
+
+        ArtCacheInfo {
+            entries: entries.len(),
+            size: entries.iter().map(|(_, bytes)| bytes.len() as u64).sum(),
+            disk_entries: disk_files.len(),
+            disk_size: disk_files.iter().map(|(_, _, len)| len).sum(),
+            directory: self
+                .directory
+                .as_ref()
+                .map(|x| x.to_string_lossy().to_string()),
+        }
+    }
+
+    /// Forgets everything, including what's on disk
+    pub fn clear(&self) -> anyhow::Result<()> {
+        self.entries.lock().unwrap().clear();
+        self.current.lock().unwrap().clear();
+        self.details.lock().unwrap().clear();
+        self.decoded.lock().unwrap().take();
+
+        // Only what we put there, in case it's pointed at a folder with other things in it
+        if let Some(directory) = &self.directory {
+            for (path, _, _) in cache_files(directory) {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("media-fetcher-art-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn decode_refuses_huge_images() {
+        let png = |width, height| {
+            let mut bytes = Vec::new();
+            DynamicImage::new_luma8(width, height)
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .unwrap();
+            bytes
+        };
+
+        assert!(decode(&png(640, 640)).is_ok());
+        assert!(decode(&png(10000, 1)).is_err());
+        assert!(decode(b"not an image").is_err());
+    }
+
+    #[test]
+    fn cache_file_names() {
+        let key = cache_key("cover");
+        for options in [
+            ArtOptions::default(),
+            ArtOptions {
+                format: ArtFormat::Jpeg,
+                ..Default::default()
+            },
+            ArtOptions {
+                format: ArtFormat::Webp,
+                ..Default::default()
+            },
+        ] {
+            assert!(is_cache_file(&file_name(&key, &options)));
+        }
+        assert!(is_cache_file(&format!("{key}{DETAILS_SUFFIX}")));
+
+        for name in [
+            "cover.png",
+            "0123456789abcdef.png",
+            "0123456789ABCDEF-512.png",
+            "0123456789abcde-512.png",
+            "0123456789abcdef-512.gif",
+            "0123456789abcdef-big.png",
+            "0123456789abcdef-512-q.jpg",
+            "0123456789abcdef-512.png.bak",
+            "0123456789abcdef-notes.json",
+            // Could point outside the cache
+            "../0123456789abcdef-512.png",
+            "C:0123456789abcdef-512.png",
+            "0123456789abcdef-512.png/x.png",
+            "0123456789abcdef-5/../12.png",
+        ] {
+            assert!(!is_cache_file(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn clear_leaves_other_files_alone() {
+        let dir = temp_dir("clear");
+        let ours = format!("{}-512.png", cache_key("cover"));
+        std::fs::write(dir.join(&ours), "art").unwrap();
+        std::fs::write(dir.join("holiday.png"), "photo").unwrap();
+
+        ArtCache::new(Some(dir.clone())).clear().unwrap();
+        let exists = (dir.join(&ours).exists(), dir.join("holiday.png").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(exists, (false, true));
+    }
+
+    #[test]
+    fn evict_removes_oldest_first() {
+        let dir = temp_dir("evict");
+        let names = ["old", "middle", "new"].map(|x| format!("{}-512.png", cache_key(x)));
+        let now = SystemTime::now();
+        for (i, name) in names.iter().enumerate() {
+            let file = std::fs::File::create(dir.join(name)).unwrap();
+            file.set_len(10).unwrap();
+            file.set_modified(now - Duration::from_secs(60 - i as u64))
+                .unwrap();
+        }
+        std::fs::write(dir.join("unrelated.png"), [0; 100]).unwrap();
+
+        evict(&dir, 20);
+        let exists = names.map(|x| dir.join(x).exists());
+        let unrelated = dir.join("unrelated.png").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(exists, [false, true, true]);
+        assert!(unrelated);
+    }
+}
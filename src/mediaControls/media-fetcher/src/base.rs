@@ -1,5 +1,6 @@
-use crate::proto;
+use crate::{art::ArtCache, proto};
 use async_trait::async_trait;
+use std::sync::Arc;
 use tokio::io::AsyncBufReadExt;
 
 #[async_trait(?Send)]
@@ -7,6 +8,7 @@ pub trait MediaFetcher {
     async fn init(&mut self) -> anyhow::Result<()>;
     async fn run(&self) -> anyhow::Result<()>;
     async fn handle_command(&self, request: proto::Request) -> anyhow::Result<()>;
+    fn art_cache(&self) -> Arc<ArtCache>;
 }
 
 pub fn send_response(response: proto::Response) -> anyhow::Result<()> {
@@ -0,0 +1,50 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Provides information about the system media player to Media Controls
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+pub struct Args {
+    /// Only switch to these players automatically, by name (e.g. `Spotify`) or MPRIS bus name (e.g. `spotify`). Can be repeated
+    #[arg(long = "allow-player", value_name = "PLAYER")]
+    pub allowed_players: Vec<String>,
+
+    /// Never switch to these players automatically, though they can still be selected by hand. Can be repeated
+    #[arg(long = "deny-player", value_name = "PLAYER")]
+    pub denied_players: Vec<String>,
+
+    /// Also keep album art in this directory, so it doesn't have to be made again after a restart.
+    /// Only the files named like ones we made are ever deleted, oldest first past 64 MiB
+    #[arg(long, value_name = "DIR")]
+    pub art_cache_dir: Option<PathBuf>,
+
+    /// Link album art from a server on localhost instead of sending it inline as base64
+    #[arg(long)]
+    pub serve_art: bool,
+}
+
+impl Args {
+    pub fn player_filter(&self) -> PlayerFilter {
+        let lowercase = |list: &[String]| list.iter().map(|x| x.to_lowercase()).collect();
+        PlayerFilter {
+            allowed: lowercase(&self.allowed_players),
+            denied: lowercase(&self.denied_players),
+        }
+    }
+}
+
+/// Decides which players can be followed automatically, so e.g. browser tabs don't take over from the music player
+#[derive(Clone, Debug, Default)]
+pub struct PlayerFilter {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+}
+
+impl PlayerFilter {
+    /// `names` is everything the player goes by, compared case insensitively
+    pub fn follows(&self, names: &[&str]) -> bool {
+        let names = names.iter().map(|x| x.to_lowercase()).collect::<Vec<_>>();
+        let listed = |list: &[String]| list.iter().any(|x| names.contains(x));
+        !listed(&self.denied) && (self.allowed.is_empty() || listed(&self.allowed))
+    }
+}
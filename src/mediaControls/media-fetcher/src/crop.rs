@@ -0,0 +1,280 @@
+use image::{DynamicImage, GenericImageView, GrayImage};
+
+// How far apart two colors can be on any channel and still count as the same, for compression noise
+const TOLERANCE: u8 = 24;
+// Share of a line that can stand out from a border, e.g. a channel logo on a black bar
+const MAX_OUTLIERS: f32 = 0.02;
+// Anything this close to square is left as is
+const SQUARE_TOLERANCE: f32 = 0.05;
+// Plenty to tell blurry from sharp, and quick to go through
+const SAMPLE_SIZE: u32 = 256;
+// How much less detail the sides need than the middle to count as a blurred copy of it
+const BLUR_RATIO: f32 = 0.35;
+// Below this the middle is too plain to compare against
+const MIN_DETAIL: f32 = 2.;
+
+/// Each gets what the ones before it made, and returns `None` if it doesn't apply
+const RULES: &[fn(&DynamicImage, &str) -> Option<DynamicImage>] =
+    &[spotify_watermark, uniform_borders, blurred_sides];
+
+/// Trims what players put around the actual cover, like the bars on video thumbnails.
+/// `player` is whatever the platform calls the app, for rules that only apply to one
+pub fn crop(mut image: DynamicImage, player: &str) -> DynamicImage {
+    for rule in RULES {
+        if let Some(cropped) = rule(&image, player) {
+            image = cropped;
+        }
+    }
+    image
+}
+
+fn aspect_ratio(width: u32, height: u32) -> f32 {
+    width.max(height) as f32 / width.min(height).max(1) as f32
+}
+
+fn is_square(width: u32, height: u32) -> bool {
+    aspect_ratio(width, height) <= 1. + SQUARE_TOLERANCE
+}
+
+/// Spotify free accounts on Windows give a watermarked image, with the cover on a transparent background
+fn spotify_watermark(image: &DynamicImage, player: &str) -> Option<DynamicImage> {
+    if !matches!(
+        player,
+        "Spotify.exe" | "SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify"
+    ) {
+        return None;
+    }
+
+    // Where the cover is in the watermarked image
+    let (x, y, side) = (34, 1, 233);
+    if image.width() < x + side || image.height() < y + side {
+        return None;
+    }
+
+    let [r, g, b, a] = image.get_pixel(0, 0).0;
+    (r == 0 && g == 0 && b == 0 && a == 0).then(|| image.crop_imm(x, y, side, side))
+}
+
+fn same_color(a: [u8; 4], b: [u8; 4]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a.abs_diff(b) <= TOLERANCE)
+}
+
+/// Whether a row or column is all one color, give or take a few pixels
+fn is_uniform(pixels: impl ExactSizeIterator<Item = [u8; 4]> + Clone) -> bool {
+    let len = pixels.len();
+    let mut sorted = pixels.clone().collect::<Vec<_>>();
+    sorted.sort_unstable();
+    let Some(&reference) = sorted.get(len / 2) else {
+        return false;
+    };
+
+    let outliers = pixels.filter(|x| !same_color(*x, reference)).count();
+    outliers as f32 <= len as f32 * MAX_OUTLIERS
+}
+
+/// Solid bars around the cover, like black letterboxing or a plain background it was pasted on
+fn uniform_borders(image: &DynamicImage, _player: &str) -> Option<DynamicImage> {
+    let (width, height) = image.dimensions();
+    // Square covers can have a plain frame on purpose
+    if is_square(width, height) {
+        return None;
+    }
+
+    let pixels = &image.to_rgba8();
+    let row = |y: u32, left: u32, right: u32| (left..right).map(move |x| pixels.get_pixel(x, y).0);
+    let column =
+        |x: u32, top: u32, bottom: u32| (top..bottom).map(move |y| pixels.get_pixel(x, y).0);
+
+    // Rows go first, the columns only need checking between them
+    let mut top = 0;
+    while top < height / 2 && is_uniform(row(top, 0, width)) {
+        top += 1;
+    }
+    let mut bottom = height;
+    while bottom > height / 2 && is_uniform(row(bottom - 1, 0, width)) {
+        bottom -= 1;
+    }
+    let mut left = 0;
+    while left < width / 2 && is_uniform(column(left, top, bottom)) {
+        left += 1;
+    }
+    let mut right = width;
+    while right > width / 2 && is_uniform(column(right - 1, top, bottom)) {
+        right -= 1;
+    }
+
+    let (new_width, new_height) = (right.saturating_sub(left), bottom.saturating_sub(top));
+    // Nothing there, or a plain image that would be cut down to nothing
+    if new_width * 4 < width || new_height * 4 < height {
+        return None;
+    }
+    // Only worth it if it gets closer to the square cover that should be in there
+    if aspect_ratio(new_width, new_height) >= aspect_ratio(width, height) {
+        return None;
+    }
+
+    Some(image.crop_imm(left, top, new_width, new_height))
+}
+
+/// How much neighbouring pixels differ on average, which is low where the picture is blurry
+fn detail(luma: &GrayImage, x: u32, y: u32, width: u32, height: u32) -> f32 {
+    let mut total = 0u64;
+    let mut count = 0u64;
+    for y in y..(y + height).saturating_sub(1) {
+        for x in x..(x + width).saturating_sub(1) {
+            let here = luma.get_pixel(x, y).0[0];
+            total += here.abs_diff(luma.get_pixel(x + 1, y).0[0]) as u64;
+            total += here.abs_diff(luma.get_pixel(x, y + 1).0[0]) as u64;
+            count += 2;
+        }
+    }
+    total as f32 / count.max(1) as f32
+}
+
+/// A square cover in the middle of a wide frame, with blurred copies of it filling the sides
+fn blurred_sides(image: &DynamicImage, _player: &str) -> Option<DynamicImage> {
+    let (width, height) = image.dimensions();
+    if is_square(width, height) {
+        return None;
+    }
+
+    let luma = image.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_luma8();
+    let (sample_width, sample_height) = luma.dimensions();
+    let side = sample_width.min(sample_height);
+    let horizontal = sample_width > sample_height;
+    let offset = (sample_width.max(sample_height) - side) / 2;
+
+    let (middle, before, after) = if horizontal {
+        (
+            detail(&luma, offset, 0, side, side),
+            detail(&luma, 0, 0, offset, side),
+            detail(&luma, offset + side, 0, sample_width - offset - side, side),
+        )
+    } else {
+        (
+            detail(&luma, 0, offset, side, side),
+            detail(&luma, 0, 0, side, offset),
+            detail(&luma, 0, offset + side, side, sample_height - offset - side),
+        )
+    };
+
+    if middle < MIN_DETAIL || before.max(after) > middle * BLUR_RATIO {
+        return None;
+    }
+
+    let side = width.min(height);
+    Some(if horizontal {
+        image.crop_imm((width - side) / 2, 0, side, side)
+    } else {
+        image.crop_imm(0, (height - side) / 2, side, side)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// Sharp, busy and colorful, like a real cover
+    fn cover_pixel(x: u32, y: u32) -> Rgba<u8> {
+        let hash = x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663);
+        Rgba([hash as u8, (hash >> 8) as u8, (hash >> 16) as u8, 255])
+    }
+
+    /// A `side` square cover at `left`, `top` in an image filled with `background`
+    fn framed(
+        width: u32,
+        height: u32,
+        (left, top, side): (u32, u32, u32),
+        background: impl Fn(u32, u32) -> Rgba<u8>,
+    ) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            if (left..left + side).contains(&x) && (top..top + side).contains(&y) {
+                cover_pixel(x, y)
+            } else {
+                background(x, y)
+            }
+        }))
+    }
+
+    fn black(_: u32, _: u32) -> Rgba<u8> {
+        Rgba([0, 0, 0, 255])
+    }
+
+    #[test]
+    fn pillarboxed_video_thumbnail() {
+        let image = framed(320, 180, (70, 0, 180), black);
+        let cropped = crop(image.clone(), "");
+        assert_eq!(cropped.dimensions(), (180, 180));
+        assert_eq!(cropped.get_pixel(0, 0), image.get_pixel(70, 0));
+    }
+
+    #[test]
+    fn letterboxed_portrait() {
+        let image = framed(180, 320, (0, 70, 180), black);
+        let cropped = crop(image.clone(), "");
+        assert_eq!(cropped.dimensions(), (180, 180));
+        assert_eq!(cropped.get_pixel(0, 0), image.get_pixel(0, 70));
+    }
+
+    #[test]
+    fn bars_with_a_logo_on_them() {
+        let image = framed(320, 180, (70, 0, 180), |x, y| {
+            // A small white logo in the corner of the left bar
+            if x < 20 && y < 3 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                black(x, y)
+            }
+        });
+        assert_eq!(crop(image, "").dimensions(), (180, 180));
+    }
+
+    #[test]
+    fn square_frame_is_left_alone() {
+        let image = framed(200, 200, (20, 20, 160), |_, _| Rgba([255, 255, 255, 255]));
+        let cropped = crop(image.clone(), "");
+        assert_eq!(cropped.dimensions(), (200, 200));
+        assert_eq!(cropped.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn blurred_sides() {
+        // Smooth gradients on either side, like a blown up and blurred copy of the cover
+        let image = framed(320, 180, (70, 0, 180), |x, y| {
+            Rgba([(x / 2) as u8, (y / 2) as u8, 128, 255])
+        });
+        assert_eq!(crop(image, "").dimensions(), (180, 180));
+    }
+
+    #[test]
+    fn uniform_image_is_left_alone() {
+        let image =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(320, 180, Rgba([40, 90, 200, 255])));
+        assert_eq!(crop(image, "").dimensions(), (320, 180));
+    }
+
+    #[test]
+    fn tiny_images() {
+        for (width, height) in [(1, 1), (1, 100), (100, 1), (2, 1)] {
+            let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, cover_pixel));
+            assert_eq!(crop(image, "").dimensions(), (width, height));
+
+            let transparent = DynamicImage::new_rgba8(width, height);
+            assert_eq!(
+                crop(transparent, "Spotify.exe").dimensions(),
+                (width, height)
+            );
+        }
+    }
+
+    #[test]
+    fn spotify_watermark() {
+        let mut image = RgbaImage::from_fn(300, 300, cover_pixel);
+        image.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        let image = DynamicImage::ImageRgba8(image);
+
+        assert_eq!(crop(image.clone(), "Spotify.exe").dimensions(), (233, 233));
+        assert_eq!(crop(image, "chromium").dimensions(), (300, 300));
+    }
+}
@@ -1,18 +1,489 @@
 use crate::{
+    album::{self, AlbumScanner},
+    art::{self, ArtCache},
     base::{MediaFetcher, send_response},
-    proto::{PlaybackStatus, Request},
+    config::{Args, PlayerFilter},
+    crop,
+    players::{PlayerSnapshot, PlayerTracker},
+    proto::{ArtImage, Capabilities, PlaybackStatus, PlayerInfo, PlaylistInfo, Request, TrackInfo},
 };
 use async_trait::async_trait;
-use base64::Engine;
-use image::imageops::FilterType::Triangle;
-use mpris::PlayerFinder;
-use std::{io::Cursor, time::Duration};
+use dbus::{
+    Message, MessageType, blocking::stdintf::org_freedesktop_dbus::Properties, message::MatchRule,
+};
+use image::DynamicImage;
+use mpris::{FindingError, Metadata, MetadataValue, Player, PlayerFinder, TrackID};
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{
+    Notify,
+    mpsc::{UnboundedReceiver, UnboundedSender},
+};
+
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const MPRIS_BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const TRACK_LIST_INTERFACE: &str = "org.mpris.MediaPlayer2.TrackList";
+// The mpris crate doesn't cover this one, so it's called directly
+const PLAYLISTS_INTERFACE: &str = "org.mpris.MediaPlayer2.Playlists";
+
+// Position doesn't emit PropertiesChanged, but the UI keeps counting up on its own between updates
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+// Used if we can't listen for signals at all
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const EVENT_DEBOUNCE: Duration = Duration::from_millis(50);
+const PLAYER_TIMEOUT_MS: i32 = 500;
+const ART_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+// Covers are a few megabytes at most, anything bigger probably isn't one
+const MAX_ART_DOWNLOAD_SIZE: u64 = 20 * 1024 * 1024;
+
+/// ID, name and icon, the way the Playlists interface sends them
+type MprisPlaylist = (dbus::Path<'static>, String, String);
+
+/// Joins xesam list properties like genres, some players send a single string instead
+fn get_strings(metadata: &Metadata, key: &str) -> String {
+    match metadata.get(key) {
+        Some(MetadataValue::String(value)) => value.clone(),
+        Some(value) => value.as_str_array().unwrap_or_default().join(", "),
+        None => String::new(),
+    }
+}
+
+/// `None` for anything that isn't a local file, like art from a website
+fn file_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let path = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .ok()?;
+    Some(PathBuf::from(path.as_ref()))
+}
 
-#[derive(Default)]
-pub struct LinuxMediaFetcher {}
+/// Gets art from a website ourselves, since some of them won't give it to Discord
+fn download(agent: &ureq::Agent, url: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(agent
+        .get(url)
+        .header("Accept", "image/*")
+        .call()?
+        .body_mut()
+        .with_config()
+        .limit(MAX_ART_DOWNLOAD_SIZE)
+        .read_to_vec()?)
+}
+
+/// Where a track's art comes from, worked out up front so it can be loaded on another thread
+enum ArtSource {
+    File(PathBuf),
+    Web {
+        url: String,
+        agent: ureq::Agent,
+    },
+    /// Embedded in the track, or an image next to it
+    Track(PathBuf),
+}
+
+impl ArtSource {
+    fn new(metadata: &Metadata, agent: &ureq::Agent) -> Option<Self> {
+        let url = metadata.art_url().unwrap_or_default();
+        if let Some(path) = file_path(url) {
+            Some(Self::File(path))
+        } else if url.starts_with("https://") || url.starts_with("http://") {
+            Some(Self::Web {
+                url: url.to_string(),
+                agent: agent.clone(),
+            })
+        } else if url.is_empty() {
+            // Local players often leave out the art URL, but the file usually has a cover
+            metadata.url().and_then(file_path).map(Self::Track)
+        } else {
+            None
+        }
+    }
+
+    fn key(&self) -> String {
+        // Part of the cache key, so a replaced cover doesn't keep showing the old one
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|x| x.modified()).ok();
+        match self {
+            Self::File(path) => art::cache_key((path, modified(path))),
+            Self::Web { url, .. } => art::cache_key(url),
+            // Adding a cover.jpg next to it changes the folder's modification time
+            Self::Track(track) => {
+                art::cache_key((track, modified(track), track.parent().and_then(modified)))
+            }
+        }
+    }
+
+    fn load(&self, player: &str) -> anyhow::Result<DynamicImage> {
+        let data = match self {
+            Self::File(path) => std::fs::read(path)?,
+            Self::Web { url, agent } => download(agent, url)?,
+            Self::Track(track) => {
+                let Some(data) = album::embedded_art(track).or_else(|| album::folder_art(track))
+                else {
+                    anyhow::bail!("Track has no cover art");
+                };
+                data
+            }
+        };
+        Ok(crop::crop(art::decode(&data)?, player))
+    }
+
+    /// Sends the placeholder first if `with_placeholder`, since it's ready well before the art is
+    fn send(
+        &self,
+        art: &ArtCache,
+        player: &str,
+        track_key: String,
+        with_placeholder: bool,
+    ) -> anyhow::Result<()> {
+        let sizes = art.sizes();
+        let hash = self.key();
+        // A download that failed for the placeholder would likely just time out again
+        let failed = Cell::new(false);
+        let load = || {
+            anyhow::ensure!(!failed.get(), "Couldn't load the album art earlier");
+            self.load(player).inspect_err(|_| failed.set(true))
+        };
+
+        if with_placeholder && let Some(placeholder) = art.placeholder(&hash, load) {
+            send_response(crate::proto::Response::ArtPlaceholder {
+                track_key: track_key.clone(),
+                placeholder,
+            })?;
+        }
+
+        let (images, palette) = match art.get_or_insert(&hash, &sizes, load) {
+            Ok((images, palette)) => (images, Some(palette)),
+            Err(e) => {
+                let Self::Web { url, .. } = self else {
+                    return Err(e);
+                };
+                // The client can still try loading it itself, at whatever size it is
+                eprintln!("Failed to download album art from {}: {:?}", url, e);
+                let images = sizes
+                    .iter()
+                    .map(|options| ArtImage {
+                        options: *options,
+                        data: url.clone(),
+                    })
+                    .collect();
+                (images, None)
+            }
+        };
+
+        send_response(crate::proto::Response::AlbumArt {
+            images,
+            track_key,
+            hash,
+            palette,
+        })
+    }
+
+    /// Decoding and downloading can take a while, so this doesn't hold up status updates or commands
+    fn send_in_background(
+        self,
+        art: Arc<ArtCache>,
+        player: String,
+        track_key: String,
+        with_placeholder: bool,
+    ) {
+        std::thread::spawn(move || {
+            if let Err(e) = self.send(&art, &player, track_key, with_placeholder) {
+                eprintln!("Error sending album art: {:?}", e);
+            }
+        });
+    }
+}
+
+/// What a signal told us might have changed
+#[derive(Clone, Copy, PartialEq)]
+enum Event {
+    Player,
+    TrackList,
+}
+
+/// Blocks forever, notifying `tx` whenever a player changes, seeks, appears or disappears,
+/// or its track list changes
+fn watch_signals(tx: UnboundedSender<Event>) -> anyhow::Result<()> {
+    let conn = dbus::blocking::Connection::new_session()?;
+    // The first rule matching a signal would swallow it otherwise
+    conn.set_signal_match_mode(true);
+
+    let properties_changed =
+        MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged")
+            .with_path(MPRIS_PATH);
+    let seeked =
+        MatchRule::new_signal("org.mpris.MediaPlayer2.Player", "Seeked").with_path(MPRIS_PATH);
+    let name_owner_changed = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged")
+        .with_sender("org.freedesktop.DBus");
+
+    // TrackListReplaced, TrackAdded, TrackRemoved and TrackMetadataChanged
+    let track_list = MatchRule::new()
+        .with_type(MessageType::Signal)
+        .with_interface(TRACK_LIST_INTERFACE)
+        .with_path(MPRIS_PATH);
+
+    {
+        let tx = tx.clone();
+        // Some players only invalidate the Tracks property instead of sending the signals above
+        conn.add_match(properties_changed, move |_: (), _, msg: &Message| {
+            let event = if msg.read1::<&str>().is_ok_and(|x| x == TRACK_LIST_INTERFACE) {
+                Event::TrackList
+            } else {
+                Event::Player
+            };
+            tx.send(event).is_ok()
+        })?;
+    }
+
+    for (rule, event) in [(seeked, Event::Player), (track_list, Event::TrackList)] {
+        let tx = tx.clone();
+        conn.add_match(rule, move |_: (), _, _| tx.send(event).is_ok())?;
+    }
+
+    conn.add_match(
+        name_owner_changed,
+        move |(name, _, _): (String, String, String), _, _| {
+            !name.starts_with(MPRIS_BUS_NAME_PREFIX) || tx.send(Event::Player).is_ok()
+        },
+    )?;
+
+    loop {
+        conn.process(Duration::from_secs(60))?;
+    }
+}
+
+/// Waits until a player might have changed, or it's time to poll anyways.
+/// Returns whether a track list changed too.
+async fn wait_for_change(events: &mut Option<UnboundedReceiver<Event>>, refresh: &Notify) -> bool {
+    let Some(rx) = events else {
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {},
+            _ = refresh.notified() => {},
+        }
+        return false;
+    };
+
+    let event = tokio::select! {
+        event = rx.recv() => event,
+        _ = refresh.notified() => Some(Event::Player),
+        // Nothing happened, check anyways in case we missed something
+        _ = tokio::time::sleep(FALLBACK_POLL_INTERVAL) => Some(Event::Player),
+    };
+
+    let Some(mut event) = event else {
+        eprintln!("MPRIS signal watcher stopped, polling instead");
+        *events = None;
+        return false;
+    };
+
+    // Players usually change a few properties at once, so wait for the rest
+    tokio::time::sleep(EVENT_DEBOUNCE).await;
+    while let Ok(next) = rx.try_recv() {
+        if next == Event::TrackList {
+            event = next;
+        }
+    }
+
+    event == Event::TrackList
+}
+
+pub struct LinuxMediaFetcher {
+    filter: PlayerFilter,
+    tracker: Arc<Mutex<PlayerTracker>>,
+    /// Wakes up the status loop early, e.g. after switching players
+    refresh: Arc<Notify>,
+    albums: Arc<AlbumScanner>,
+    art: Arc<ArtCache>,
+    /// Shared by every art download, so connections to the same site get reused
+    agent: ureq::Agent,
+}
 
 impl LinuxMediaFetcher {
-    async fn get_status(player: &mpris::Player) -> anyhow::Result<PlaybackStatus> {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            filter: args.player_filter(),
+            tracker: Default::default(),
+            refresh: Default::default(),
+            albums: Default::default(),
+            art: Arc::new(ArtCache::new(args.art_cache_dir.clone())),
+            agent: ureq::Agent::config_builder()
+                .timeout_global(Some(ART_DOWNLOAD_TIMEOUT))
+                .user_agent(concat!("media-fetcher/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .into(),
+        }
+    }
+
+    fn get_snapshot(player: &Player, filter: &PlayerFilter) -> PlayerSnapshot {
+        let track = player
+            .get_metadata()
+            .map(|metadata| {
+                format!(
+                    "{}\n{}\n{}",
+                    metadata.title().unwrap_or_default(),
+                    metadata.artists().unwrap_or_default().join(", "),
+                    metadata.album_name().unwrap_or_default()
+                )
+            })
+            .unwrap_or_default();
+
+        PlayerSnapshot {
+            id: player.bus_name().to_string(),
+            playing: player
+                .get_playback_status()
+                .is_ok_and(|x| x == mpris::PlaybackStatus::Playing),
+            track,
+            followable: filter.follows(&[player.identity(), player.bus_name_player_name_part()]),
+        }
+    }
+
+    fn get_players(
+        players: &[Player],
+        snapshots: &[PlayerSnapshot],
+        tracker: &PlayerTracker,
+    ) -> Vec<PlayerInfo> {
+        players
+            .iter()
+            .zip(snapshots)
+            .map(|(player, snapshot)| PlayerInfo {
+                id: snapshot.id.clone(),
+                name: player.identity().to_string(),
+                playing: snapshot.playing,
+                selected: tracker.active() == Some(&snapshot.id),
+                pinned: tracker.pinned() == Some(&snapshot.id),
+            })
+            .collect()
+    }
+
+    fn get_track_info(metadata: &Metadata, current: Option<&TrackID>) -> TrackInfo {
+        let id = metadata.track_id();
+        TrackInfo {
+            id: id.as_ref().map(|x| x.to_string()).unwrap_or_default(),
+            title: metadata.title().unwrap_or_default().to_string(),
+            artist: metadata.artists().unwrap_or_default().join(", "),
+            album: metadata.album_name().unwrap_or_default().to_string(),
+            duration: metadata
+                .length()
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default(),
+            current: id.is_some() && id.as_ref() == current,
+        }
+    }
+
+    /// Empty if the player doesn't have a track list
+    fn get_track_list(player: &Player) -> anyhow::Result<Vec<TrackInfo>> {
+        if !player.get_has_track_list().unwrap_or(false) {
+            return Ok(Vec::new());
+        }
+
+        let current = player.get_metadata().ok().and_then(|x| x.track_id());
+        let track_list = player.get_track_list()?;
+        let tracks = player
+            .get_tracks_metadata(track_list.ids())?
+            .iter()
+            .map(|metadata| LinuxMediaFetcher::get_track_info(metadata, current.as_ref()))
+            .collect();
+        Ok(tracks)
+    }
+
+    /// Empty if the player doesn't have playlists
+    fn get_playlists(player: &Player) -> anyhow::Result<Vec<PlaylistInfo>> {
+        let connection = dbus::blocking::Connection::new_session()?;
+        let proxy = connection.with_proxy(
+            player.bus_name(),
+            MPRIS_PATH,
+            Duration::from_millis(PLAYER_TIMEOUT_MS as u64),
+        );
+
+        let Ok(count) = proxy.get::<u32>(PLAYLISTS_INTERFACE, "PlaylistCount") else {
+            return Ok(Vec::new());
+        };
+
+        // Players only have to support the orderings they list, prefer the one the user made
+        let orderings: Vec<String> = proxy
+            .get(PLAYLISTS_INTERFACE, "Orderings")
+            .unwrap_or_default();
+        let order = orderings
+            .iter()
+            .find(|x| *x == "UserDefined")
+            .or(orderings.first())
+            .map_or("Alphabetical", |x| x.as_str());
+
+        let (playlists,): (Vec<MprisPlaylist>,) = proxy.method_call(
+            PLAYLISTS_INTERFACE,
+            "GetPlaylists",
+            (0_u32, count, order, false),
+        )?;
+        let active = proxy
+            .get::<(bool, MprisPlaylist)>(PLAYLISTS_INTERFACE, "ActivePlaylist")
+            .ok()
+            .filter(|(valid, _)| *valid)
+            .map(|(_, (id, _, _))| id);
+
+        Ok(playlists
+            .into_iter()
+            .map(|(id, name, icon)| PlaylistInfo {
+                active: active.as_ref() == Some(&id),
+                id: id.to_string(),
+                name,
+                icon,
+            })
+            .collect())
+    }
+
+    fn activate_playlist(player: &Player, id: String) -> anyhow::Result<()> {
+        let connection = dbus::blocking::Connection::new_session()?;
+        let proxy = connection.with_proxy(
+            player.bus_name(),
+            MPRIS_PATH,
+            Duration::from_millis(PLAYER_TIMEOUT_MS as u64),
+        );
+        let id = dbus::Path::new(id).map_err(anyhow::Error::msg)?;
+        proxy.method_call::<(), _, _, _>(PLAYLISTS_INTERFACE, "ActivatePlaylist", (id,))?;
+        Ok(())
+    }
+
+    fn active_player(&self) -> anyhow::Result<Player> {
+        let bus_name = self
+            .tracker
+            .lock()
+            .unwrap()
+            .active()
+            .ok_or(FindingError::NoPlayerFound)?
+            .to_string();
+        let connection = dbus::ffidisp::Connection::new_session()?;
+        Ok(Player::new(connection, bus_name, PLAYER_TIMEOUT_MS)?)
+    }
+
+    fn get_capabilities(player: &Player) -> Capabilities {
+        // The spec says every other Can* property is meaningless without CanControl
+        let can_control = player.can_control().unwrap_or(false);
+        Capabilities {
+            can_play: can_control && player.can_play().unwrap_or(false),
+            can_pause: can_control && player.can_pause().unwrap_or(false),
+            can_seek: can_control && player.can_seek().unwrap_or(false),
+            can_go_next: can_control && player.can_go_next().unwrap_or(false),
+            can_go_previous: can_control && player.can_go_previous().unwrap_or(false),
+            // Shuffle and LoopStatus are optional, these check whether the player has them at all
+            can_shuffle: can_control && player.can_shuffle().unwrap_or(false),
+            can_loop: can_control && player.can_loop().unwrap_or(false),
+            // Players only have to support 1x, in which case both of these are 1 or missing
+            can_set_rate: can_control
+                && player.get_minimum_playback_rate().unwrap_or(1.)
+                    < player.get_maximum_playback_rate().unwrap_or(1.),
+            can_control,
+        }
+    }
+
+    /// `metadata` is passed in so the art can be found from the same snapshot the status was made from
+    async fn get_status(
+        player: &mpris::Player,
+        metadata: Option<&Metadata>,
+        albums: &AlbumScanner,
+    ) -> anyhow::Result<PlaybackStatus> {
         let playback_status = player
             .get_playback_status()
             .unwrap_or(mpris::PlaybackStatus::Stopped);
@@ -34,13 +505,27 @@ impl LinuxMediaFetcher {
             shuffle: player.get_shuffle().unwrap_or(false),
             track_number: 0,
             total_tracks: 0,
+            genre: String::new(),
+            year: 0,
+            disc_number: 0,
+            total_discs: 0,
+            composer: String::new(),
+            lyricist: String::new(),
+            url: String::new(),
+            track_id: String::new(),
+            user_rating: None,
+            volume: player.get_volume().ok(),
+            rate: player.get_playback_rate().unwrap_or(1.),
+            capabilities: LinuxMediaFetcher::get_capabilities(player),
+            track_key: String::new(),
+            art_placeholder: None,
         };
 
         if let Ok(position) = player.get_position() {
             status.elapsed = position.as_secs_f64();
         }
 
-        if let Ok(metadata) = player.get_metadata() {
+        if let Some(metadata) = metadata {
             status.title = metadata.title().unwrap_or_default().to_string();
             status.artist = metadata
                 .artists()
@@ -58,14 +543,43 @@ impl LinuxMediaFetcher {
                 .collect::<Vec<String>>()
                 .join(", ");
             status.track_number = metadata.track_number().unwrap_or_default();
-            // mpris nor xesam don't have a total track count property :(
             status.total_tracks = status.track_number;
             status.duration = metadata
                 .length()
                 .map(|d| d.as_secs_f64())
                 .unwrap_or_default();
+            status.genre = get_strings(metadata, "xesam:genre");
+            // A date like "2007-04-29T14:35:51", or sometimes just the year
+            status.year = metadata
+                .get("xesam:contentCreated")
+                .and_then(|x| x.as_str())
+                .and_then(|x| x.get(..4))
+                .and_then(|x| x.parse().ok())
+                .unwrap_or_default();
+            status.disc_number = metadata.disc_number().unwrap_or_default();
+            status.composer = get_strings(metadata, "xesam:composer");
+            status.lyricist = get_strings(metadata, "xesam:lyricist");
+            status.url = metadata.url().unwrap_or_default().to_string();
+            status.track_id = metadata
+                .track_id()
+                .map(|x| x.to_string())
+                .unwrap_or_default();
+            status.user_rating = metadata.get("xesam:userRating").and_then(|x| x.as_f64());
+
+            // mpris nor xesam don't have a total track count property :(
+            // so look at the rest of the album instead if it's on disk
+            if let Some(path) = file_path(&status.url)
+                && let Some(album) = albums.get_album(&path).await
+            {
+                status.total_tracks = status.total_tracks.max(album.total_tracks);
+                status.total_discs = status.disc_number.max(album.total_discs);
+                if status.album_artist.is_empty() {
+                    status.album_artist = album.album_artist;
+                }
+            }
         }
 
+        status.track_key = art::track_key(&status);
         Ok(status)
     }
 }
@@ -79,36 +593,124 @@ impl MediaFetcher for LinuxMediaFetcher {
     async fn run(&self) -> anyhow::Result<()> {
         let finder = PlayerFinder::new()?;
 
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            if let Err(e) = watch_signals(tx) {
+                eprintln!("Error watching MPRIS signals: {:?}", e);
+            }
+        });
+
+        let filter = self.filter.clone();
+        let tracker = self.tracker.clone();
+        let refresh = self.refresh.clone();
+        let albums = self.albums.clone();
+        let art = self.art.clone();
+        let agent = self.agent.clone();
+
         let local = tokio::task::LocalSet::new();
         local
             .run_until(async move {
                 tokio::task::spawn_local(async move {
                     let mut prev_session = PlaybackStatus::default();
+                    let mut prev_players = Vec::new();
+                    let mut prev_tracks = Vec::new();
+                    // Which player and track the track list was last fetched for
+                    let mut prev_track_list_key = None;
+                    let mut track_list_changed = false;
+                    let mut events = Some(rx);
 
                     loop {
-                        let new_status = {
-                            if let Ok(player) = finder.find_first() {
-                                if let Ok(result) = LinuxMediaFetcher::get_status(&player).await {
-                                    // Everything is playing
-                                    result
-                                } else {
-                                    // Something went wrong, let's pretend nothing is playing
-                                    PlaybackStatus::default()
-                                }
-                            } else {
-                                // Nothing is playing
-                                PlaybackStatus::default()
+                        let players = finder
+                            .iter_players()
+                            .map(|x| x.filter_map(Result::ok).collect::<Vec<_>>())
+                            .unwrap_or_default();
+
+                        let snapshots = players
+                            .iter()
+                            .map(|x| LinuxMediaFetcher::get_snapshot(x, &filter))
+                            .collect::<Vec<_>>();
+
+                        let (player, new_players) = {
+                            let mut tracker = tracker.lock().unwrap();
+                            tracker.update(snapshots.clone());
+                            let player = tracker
+                                .active()
+                                .and_then(|id| players.iter().find(|x| x.bus_name() == id));
+                            let new_players =
+                                LinuxMediaFetcher::get_players(&players, &snapshots, &tracker);
+                            (player, new_players)
+                        };
+
+                        // Also refetch when the current track changes, so `current` stays right
+                        let track_list_key = player.and_then(|player| {
+                            snapshots
+                                .iter()
+                                .find(|x| x.id == player.bus_name())
+                                .map(|x| (x.id.clone(), x.track.clone()))
+                        });
+                        if track_list_changed || prev_track_list_key != track_list_key {
+                            let new_tracks = player
+                                .and_then(|x| LinuxMediaFetcher::get_track_list(x).ok())
+                                .unwrap_or_default();
+                            if prev_tracks != new_tracks {
+                                send_response(crate::proto::Response::TrackList {
+                                    tracks: new_tracks.clone(),
+                                })?;
+                                prev_tracks = new_tracks;
                             }
+                            prev_track_list_key = track_list_key;
+                        }
+
+                        if prev_players != new_players {
+                            send_response(crate::proto::Response::Players {
+                                players: new_players.clone(),
+                            })?;
+                            prev_players = new_players;
+                        }
+
+                        let metadata = player.and_then(|x| x.get_metadata().ok());
+                        let mut new_status = if let Some(player) = player {
+                            // Something went wrong, let's pretend nothing is playing
+                            LinuxMediaFetcher::get_status(player, metadata.as_ref(), &albums)
+                                .await
+                                .unwrap_or_default()
+                        } else {
+                            // Nothing is playing
+                            PlaybackStatus::default()
                         };
 
                         if prev_session != new_status {
-                            send_response(crate::proto::Response::PlaybackStatus(
+                            let art_source = player
+                                .filter(|_| {
+                                    prev_session.track_key != new_status.track_key
+                                        && !new_status.track_key.is_empty()
+                                })
+                                .and_then(|player| {
+                                    let source = ArtSource::new(metadata.as_ref()?, &agent)?;
+                                    Some((source, player.identity().to_string()))
+                                });
+                            // Only if it's cached, anything else would hold up the status
+                            if let Some((source, _)) = &art_source {
+                                new_status.art_placeholder = art.cached_placeholder(&source.key());
+                            }
+
+                            send_response(crate::proto::Response::PlaybackStatus(Box::new(
                                 new_status.clone(),
-                            ))?;
+                            )))?;
+
+                            if let Some((source, player)) = art_source {
+                                source.send_in_background(
+                                    art.clone(),
+                                    player,
+                                    new_status.track_key.clone(),
+                                    new_status.art_placeholder.is_none(),
+                                );
+                            }
+
                             prev_session = new_status;
                         }
 
-                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        track_list_changed = wait_for_change(&mut events, &refresh).await;
                     }
 
                     #[allow(unreachable_code)]
@@ -121,56 +723,57 @@ impl MediaFetcher for LinuxMediaFetcher {
         Ok(())
     }
 
-    async fn handle_command(&self, request: Request) -> anyhow::Result<()> {
-        let player = PlayerFinder::new()?.find_first()?;
+    fn art_cache(&self) -> Arc<ArtCache> {
+        self.art.clone()
+    }
 
+    async fn handle_command(&self, request: Request) -> anyhow::Result<()> {
         match request {
-            Request::GetAlbumArt => {
-                let str = player
-                    .get_metadata()?
-                    .art_url()
-                    .unwrap_or_default()
-                    .to_string();
-                if str.starts_with("file://") {
-                    let path = str.trim_start_matches("file://");
-                    let path = percent_encoding::percent_decode_str(path).decode_utf8()?;
-                    let path = path.to_string();
-
-                    let mut image = image::load_from_memory(&std::fs::read(&path)?)?;
-
-                    if image.width() > 1000 {
-                        let ratio = image.height() as f32 / image.width() as f32;
-                        image = image.resize(1000, (1000_f32 * ratio).ceil() as u32, Triangle);
-                    } else if image.height() > 1000 {
-                        let ratio = image.width() as f32 / image.height() as f32;
-                        image = image.resize((1000_f32 * ratio).ceil() as u32, 1000, Triangle);
-                    }
+            Request::GetAlbumArt { sizes } => {
+                // Even if there's nothing to send yet, so art sent on the next track change has these sizes
+                self.art.set_sizes(sizes);
+                let player = self.active_player()?;
+                let metadata = player.get_metadata()?;
+                let track_key =
+                    LinuxMediaFetcher::get_status(&player, Some(&metadata), &self.albums)
+                        .await?
+                        .track_key;
+                if let Some(source) = ArtSource::new(&metadata, &self.agent) {
+                    source.send_in_background(
+                        self.art.clone(),
+                        player.identity().to_string(),
+                        track_key,
+                        false,
+                    );
+                }
+            }
+
+            Request::Play => {
+                self.active_player()?.play()?;
+            }
 
-                    let mut bytes = Vec::new();
-                    image.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)?;
+            Request::Pause => {
+                self.active_player()?.pause()?;
+            }
 
-                    let base64 = base64::prelude::BASE64_STANDARD.encode(&bytes);
-                    let base64 = format!("data:image/png;base64,{}", base64);
-                    send_response(crate::proto::Response::AlbumArt { data: base64 })?;
-                } else if str.starts_with("https://") || str.starts_with("http://") {
-                    send_response(crate::proto::Response::AlbumArt { data: str })?;
-                }
+            Request::PlayPause => {
+                self.active_player()?.play_pause()?;
             }
 
-            Request::Play | Request::Pause => {
-                player.play_pause()?;
+            Request::Stop => {
+                self.active_player()?.stop()?;
             }
 
             Request::SkipBackward => {
-                player.previous()?;
+                self.active_player()?.previous()?;
             }
 
             Request::SkipForward => {
-                player.next()?;
+                self.active_player()?.next()?;
             }
 
             Request::SetRepeatMode { mode } => {
-                player.set_loop_status(match mode {
+                self.active_player()?.set_loop_status(match mode {
                     crate::proto::RepeatMode::All => mpris::LoopStatus::Playlist,
                     crate::proto::RepeatMode::One => mpris::LoopStatus::Track,
                     crate::proto::RepeatMode::None => mpris::LoopStatus::None,
@@ -178,23 +781,82 @@ impl MediaFetcher for LinuxMediaFetcher {
             }
 
             Request::SetShuffle { shuffle } => {
-                player.set_shuffle(shuffle)?;
+                self.active_player()?.set_shuffle(shuffle)?;
             }
 
             Request::Seek { position } => {
-                let track = if let Ok(track_list) = player.get_track_list()
-                    && let Some(track) = track_list.get(0)
-                {
-                    track.clone()
-                } else if let Ok(metadata) = player.get_metadata()
-                    && let Some(track_id) = metadata.track_id()
-                {
-                    track_id
-                } else {
+                let player = self.active_player()?;
+                let Ok(metadata) = player.get_metadata() else {
                     anyhow::bail!("No track is currently playing")
                 };
 
-                player.set_position(track, &Duration::from_secs_f64(position))?;
+                if let Some(track) = metadata.track_id() {
+                    player.set_position(track, &Duration::from_secs_f64(position))?;
+                } else {
+                    // Browsers usually don't give their tracks an ID, but they can still seek relative
+                    let elapsed = player.get_position()?.as_secs_f64();
+                    player.seek(((position - elapsed) * 1_000_000.) as i64)?;
+                }
+            }
+
+            Request::SeekBy { offset } => {
+                self.active_player()?.seek((offset * 1_000_000.) as i64)?;
+            }
+
+            Request::SetRate { rate } => {
+                // A rate of 0 is the same as pausing according to the spec, so don't allow it
+                anyhow::ensure!(rate > 0., "Rate has to be positive");
+                let player = self.active_player()?;
+                let min = player.get_minimum_playback_rate().unwrap_or(1.);
+                let max = player.get_maximum_playback_rate().unwrap_or(1.);
+                // Not clamp, that panics if a player reports them the wrong way around
+                player.set_playback_rate(rate.max(min).min(max))?;
+            }
+
+            Request::SetVolume { volume } => {
+                self.active_player()?.set_volume(volume)?;
+            }
+
+            Request::ChangeVolume { delta } => {
+                let player = self.active_player()?;
+                // Don't jump down to 100% if someone already turned it up past that
+                let volume = player.get_volume()?;
+                player.set_volume((volume + delta).clamp(0., volume.max(1.)))?;
+            }
+
+            Request::GetTrackList => {
+                send_response(crate::proto::Response::TrackList {
+                    tracks: LinuxMediaFetcher::get_track_list(&self.active_player()?)?,
+                })?;
+            }
+
+            Request::GoTo { track_id } => {
+                self.active_player()?
+                    .go_to(&TrackID::new(track_id).map_err(anyhow::Error::msg)?)?;
+            }
+
+            Request::GetPlaylists => {
+                send_response(crate::proto::Response::Playlists {
+                    playlists: LinuxMediaFetcher::get_playlists(&self.active_player()?)?,
+                })?;
+            }
+
+            Request::ActivatePlaylist { id } => {
+                LinuxMediaFetcher::activate_playlist(&self.active_player()?, id)?;
+            }
+
+            Request::SelectPlayer { id } => {
+                self.tracker.lock().unwrap().pin(id);
+                self.refresh.notify_one();
+            }
+
+            Request::GetArtCache => {
+                send_response(crate::proto::Response::ArtCache(self.art.info()))?;
+            }
+
+            Request::ClearArtCache => {
+                self.art.clear()?;
+                send_response(crate::proto::Response::ArtCache(self.art.info()))?;
             }
         }
 
@@ -1,19 +1,108 @@
 use crate::{
+    art::{self, ArtCache},
     base::{send_response, MediaFetcher},
-    proto::{PlaybackStatus, Request},
+    config::{Args, PlayerFilter},
+    crop,
+    proto::{Capabilities, PlaybackStatus, PlayerInfo, Request},
 };
 use async_trait::async_trait;
-use base64::Engine;
-use image::imageops::FilterType::Triangle;
+use image::DynamicImage;
 use media_remote::{
     get_now_playing_info, send_command, set_elapsed_time, Command, InfoTypes, NowPlaying, Number,
 };
-use std::io::Cursor;
+use std::sync::Arc;
 
-#[derive(Default)]
-pub struct MacMediaFetcher {}
+/// The now playing cover. MediaRemote only gives us the decoded image, so that's what gets hashed
+struct Cover {
+    image: DynamicImage,
+    player: String,
+}
+
+impl Cover {
+    fn get(now_playing: &NowPlaying) -> anyhow::Result<Self> {
+        let Some(info) = now_playing.get_info().clone() else {
+            anyhow::bail!("Failed to get now playing info");
+        };
+        let Some(image) = info.album_cover else {
+            anyhow::bail!("Failed to get cover image");
+        };
+
+        Ok(Self {
+            image,
+            player: info.bundle_name.unwrap_or_default(),
+        })
+    }
+
+    fn key(&self) -> String {
+        art::cache_key(self.image.as_bytes())
+    }
+
+    fn load(&self) -> anyhow::Result<DynamicImage> {
+        Ok(crop::crop(self.image.clone(), &self.player))
+    }
+
+    /// Sends the placeholder first if `with_placeholder`, since it's ready well before the art is
+    fn send(
+        &self,
+        art: &ArtCache,
+        track_key: String,
+        with_placeholder: bool,
+    ) -> anyhow::Result<()> {
+        let key = self.key();
+        if with_placeholder && let Some(placeholder) = art.placeholder(&key, || self.load()) {
+            send_response(crate::proto::Response::ArtPlaceholder {
+                track_key: track_key.clone(),
+                placeholder,
+            })?;
+        }
+
+        let (images, palette) = art.get_or_insert(&key, &art.sizes(), || self.load())?;
+        send_response(crate::proto::Response::AlbumArt {
+            images,
+            track_key,
+            hash: key,
+            palette: Some(palette),
+        })
+    }
+
+    /// Resizing and encoding can take a while, so this doesn't hold up status updates or commands
+    fn send_in_background(self, art: Arc<ArtCache>, track_key: String, with_placeholder: bool) {
+        std::thread::spawn(move || {
+            if let Err(e) = self.send(&art, track_key, with_placeholder) {
+                eprintln!("Error sending album art: {:?}", e);
+            }
+        });
+    }
+}
+
+pub struct MacMediaFetcher {
+    filter: PlayerFilter,
+    art: Arc<ArtCache>,
+}
 
 impl MacMediaFetcher {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            filter: args.player_filter(),
+            art: Arc::new(ArtCache::new(args.art_cache_dir.clone())),
+        }
+    }
+
+    /// For the status sent when the track changes, MediaRemote has already decoded the cover so this is quick
+    fn get_players(status: &PlaybackStatus) -> Vec<PlayerInfo> {
+        if status.player_name.is_empty() {
+            return Vec::new();
+        }
+
+        vec![PlayerInfo {
+            id: status.player_name.clone(),
+            name: status.player_name.clone(),
+            playing: status.playing,
+            selected: true,
+            pinned: false,
+        }]
+    }
+
     fn get_status(now_playing: &NowPlaying) -> anyhow::Result<PlaybackStatus> {
         let mut status = PlaybackStatus {
             player_name: String::new(),
@@ -28,6 +117,24 @@ impl MacMediaFetcher {
             shuffle: false,
             track_number: 0,
             total_tracks: 0,
+            genre: String::new(),
+            year: 0,
+            disc_number: 0,
+            total_discs: 0,
+            composer: String::new(),
+            lyricist: String::new(),
+            url: String::new(),
+            track_id: String::new(),
+            user_rating: None,
+            volume: None,
+            rate: 1.,
+            // MediaRemote doesn't say what the app supports, but we can't change the rate either way
+            capabilities: Capabilities {
+                can_set_rate: false,
+                ..Capabilities::all()
+            },
+            track_key: String::new(),
+            art_placeholder: None,
         };
 
         if let Some(info) = now_playing.get_info().as_ref() {
@@ -98,6 +205,13 @@ impl MacMediaFetcher {
                     _ => (),
                 }
             }
+            // This drops to 0 while paused, keep showing the speed it'll resume at instead
+            if let Some(InfoTypes::Number(Number::Floating(rate))) =
+                info_map.get(&format_mr_key("PlaybackRate"))
+                && *rate > 0.
+            {
+                status.rate = *rate;
+            }
             if let Some(InfoTypes::Number(Number::Signed(track))) =
                 info_map.get(&format_mr_key("TrackNumber"))
             {
@@ -108,8 +222,30 @@ impl MacMediaFetcher {
             {
                 status.total_tracks = *tracks as i32;
             }
+            if let Some(InfoTypes::Number(Number::Signed(disc))) =
+                info_map.get(&format_mr_key("DiscNumber"))
+            {
+                status.disc_number = *disc as i32;
+            }
+            if let Some(InfoTypes::Number(Number::Signed(discs))) =
+                info_map.get(&format_mr_key("TotalDiscCount"))
+            {
+                status.total_discs = *discs as i32;
+            }
+            if let Some(InfoTypes::String(genre)) = info_map.get(&format_mr_key("Genre")) {
+                status.genre = genre.clone();
+            }
+            if let Some(InfoTypes::String(composer)) = info_map.get(&format_mr_key("Composer")) {
+                status.composer = composer.clone();
+            }
+            if let Some(InfoTypes::String(id)) =
+                info_map.get(&format_mr_key("ContentItemIdentifier"))
+            {
+                status.track_id = id.clone();
+            }
         }
 
+        status.track_key = art::track_key(&status);
         Ok(status)
     }
 }
@@ -122,21 +258,58 @@ impl MediaFetcher for MacMediaFetcher {
 
     async fn run(&self) -> anyhow::Result<()> {
         let now_playing = NowPlaying::new();
+        let filter = self.filter.clone();
+        let art = self.art.clone();
 
         let local = tokio::task::LocalSet::new();
         local
             .run_until(async move {
                 tokio::task::spawn_local(async move {
                     let mut prev_session = PlaybackStatus::default();
+                    let mut prev_players = Vec::new();
 
                     loop {
-                        let new_status =
-                            MacMediaFetcher::get_status(&now_playing).unwrap_or_default();
+                        // Pretend nothing is playing if we aren't allowed to follow this app
+                        let mut new_status = MacMediaFetcher::get_status(&now_playing)
+                            .ok()
+                            .filter(|x| filter.follows(&[&x.player_name]))
+                            .unwrap_or_default();
+
+                        let new_players = MacMediaFetcher::get_players(&new_status);
+                        if prev_players != new_players {
+                            send_response(crate::proto::Response::Players {
+                                players: new_players.clone(),
+                            })?;
+                            prev_players = new_players;
+                        }
 
                         if prev_session != new_status {
-                            send_response(crate::proto::Response::PlaybackStatus(
+                            let cover = if prev_session.track_key != new_status.track_key
+                                && !new_status.track_key.is_empty()
+                            {
+                                Cover::get(&now_playing)
+                                    .inspect_err(|e| eprintln!("Error getting album art: {:?}", e))
+                                    .ok()
+                            } else {
+                                None
+                            };
+                            // Only if it's cached, anything else would hold up the status
+                            if let Some(cover) = &cover {
+                                new_status.art_placeholder = art.cached_placeholder(&cover.key());
+                            }
+
+                            send_response(crate::proto::Response::PlaybackStatus(Box::new(
                                 new_status.clone(),
-                            ))?;
+                            )))?;
+
+                            if let Some(cover) = cover {
+                                cover.send_in_background(
+                                    art.clone(),
+                                    new_status.track_key.clone(),
+                                    new_status.art_placeholder.is_none(),
+                                );
+                            }
+
                             prev_session = new_status;
                         }
 
@@ -153,42 +326,44 @@ impl MediaFetcher for MacMediaFetcher {
         Ok(())
     }
 
+    fn art_cache(&self) -> Arc<ArtCache> {
+        self.art.clone()
+    }
+
     async fn handle_command(&self, request: Request) -> anyhow::Result<()> {
         let now_playing = media_remote::NowPlaying::new();
 
         match request {
-            Request::GetAlbumArt => {
-                if let Some(info) = now_playing.get_info().as_ref() {
-                    if let Some(image) = &info.album_cover {
-                        let mut image = image.clone();
-
-                        if image.width() > 1000 {
-                            let ratio = image.height() as f32 / image.width() as f32;
-                            image = image.resize(1000, (1000_f32 * ratio).ceil() as u32, Triangle);
-                        } else if image.height() > 1000 {
-                            let ratio = image.width() as f32 / image.height() as f32;
-                            image = image.resize((1000_f32 * ratio).ceil() as u32, 1000, Triangle);
-                        }
+            Request::GetAlbumArt { sizes } => {
+                self.art.set_sizes(sizes);
+                let track_key = MacMediaFetcher::get_status(&now_playing)?.track_key;
+                Cover::get(&now_playing)?.send_in_background(self.art.clone(), track_key, false);
+            }
 
-                        let mut bytes = Vec::new();
-                        image.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)?;
-                        let base64 = base64::prelude::BASE64_STANDARD.encode(&bytes);
-                        let data = format!("data:image/png;base64,{}", base64);
-                        send_response(crate::proto::Response::AlbumArt { data })?;
-                    } else {
-                        anyhow::bail!("Failed to get cover image");
-                    }
-                } else {
-                    anyhow::bail!("Failed to get now playing info");
+            Request::Play => {
+                if !now_playing.play() {
+                    anyhow::bail!("Failed to play");
+                }
+            }
+
+            Request::Pause => {
+                if !now_playing.pause() {
+                    anyhow::bail!("Failed to pause");
                 }
             }
 
-            Request::Play | Request::Pause => {
+            Request::PlayPause => {
                 if !now_playing.toggle() {
                     anyhow::bail!("Failed to toggle play/pause");
                 }
             }
 
+            Request::Stop => {
+                if !send_command(Command::Stop) {
+                    anyhow::bail!("Failed to stop");
+                }
+            }
+
             Request::SkipBackward => {
                 if !now_playing.previous() {
                     anyhow::bail!("Failed to skip backward");
@@ -247,6 +422,57 @@ impl MediaFetcher for MacMediaFetcher {
             Request::Seek { position } => {
                 set_elapsed_time(position);
             }
+
+            Request::SeekBy { offset } => {
+                let elapsed = MacMediaFetcher::get_status(&now_playing)?.elapsed;
+                set_elapsed_time((elapsed + offset).max(0.));
+            }
+
+            Request::SetRate { .. } => {
+                anyhow::bail!("MediaRemote can't change the playback rate");
+            }
+
+            Request::SetVolume { .. } | Request::ChangeVolume { .. } => {
+                anyhow::bail!("MediaRemote can't change the volume");
+            }
+
+            Request::GetTrackList => {
+                // MediaRemote doesn't share the queue
+                send_response(crate::proto::Response::TrackList { tracks: Vec::new() })?;
+            }
+
+            Request::GoTo { .. } => {
+                anyhow::bail!("MediaRemote doesn't share the queue");
+            }
+
+            Request::GetPlaylists => {
+                // MediaRemote doesn't share playlists either
+                send_response(crate::proto::Response::Playlists {
+                    playlists: Vec::new(),
+                })?;
+            }
+
+            Request::ActivatePlaylist { .. } => {
+                anyhow::bail!("MediaRemote doesn't share playlists");
+            }
+
+            Request::GetArtCache => {
+                send_response(crate::proto::Response::ArtCache(self.art.info()))?;
+            }
+
+            Request::ClearArtCache => {
+                self.art.clear()?;
+                send_response(crate::proto::Response::ArtCache(self.art.info()))?;
+            }
+
+            Request::SelectPlayer { id } => {
+                // MediaRemote only knows about whatever is now playing
+                if let Some(id) = id
+                    && MacMediaFetcher::get_status(&now_playing)?.player_name != id
+                {
+                    anyhow::bail!("Only the now playing app can be controlled");
+                }
+            }
         }
 
         Ok(())
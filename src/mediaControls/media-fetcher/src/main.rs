@@ -1,19 +1,30 @@
+use clap::Parser;
+
+#[cfg(target_os = "linux")]
+mod album;
+mod art;
 mod base;
+mod config;
+mod crop;
+mod palette;
 mod proto;
+mod server;
 
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+#[cfg(target_os = "linux")]
+mod players;
 #[cfg(target_os = "windows")]
 mod windows;
 
-async fn input_handler(fetcher: &Box<dyn base::MediaFetcher>) -> anyhow::Result<()> {
+async fn input_handler(fetcher: &dyn base::MediaFetcher) -> anyhow::Result<()> {
     loop {
-        if let Ok(command) = base::receive_command().await {
-            if let Err(e) = fetcher.handle_command(command).await {
-                eprintln!("Error in command handler: {:?}", e);
-            }
+        if let Ok(command) = base::receive_command().await
+            && let Err(e) = fetcher.handle_command(command).await
+        {
+            eprintln!("Error in command handler: {:?}", e);
         }
     }
 }
@@ -21,28 +32,36 @@ async fn input_handler(fetcher: &Box<dyn base::MediaFetcher>) -> anyhow::Result<
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     #![allow(unused_assignments)]
+    let args = config::Args::parse();
     let mut fetcher: Option<Box<dyn base::MediaFetcher>> = None;
 
     #[cfg(target_os = "windows")]
     {
-        fetcher = Some(Box::new(windows::WindowsMediaFetcher::default()));
+        fetcher = Some(Box::new(windows::WindowsMediaFetcher::new(&args)));
     }
 
     #[cfg(target_os = "linux")]
     {
-        fetcher = Some(Box::new(linux::LinuxMediaFetcher::default()));
+        fetcher = Some(Box::new(linux::LinuxMediaFetcher::new(&args)));
     }
 
     #[cfg(target_os = "macos")]
     {
-        fetcher = Some(Box::new(macos::MacMediaFetcher::default()));
+        fetcher = Some(Box::new(macos::MacMediaFetcher::new(&args)));
     }
 
     if let Some(mut fetcher) = fetcher {
         fetcher.init().await?;
 
+        // Art just gets sent inline if this doesn't work out
+        if args.serve_art
+            && let Err(e) = server::start(fetcher.art_cache()).await
+        {
+            eprintln!("Error starting album art server: {:?}", e);
+        }
+
         tokio::select! {
-            input_result = input_handler(&fetcher) => {
+            input_result = input_handler(fetcher.as_ref()) => {
                 if let Err(e) = input_result {
                     eprintln!("Error in input handler: {:?}", e);
                 }
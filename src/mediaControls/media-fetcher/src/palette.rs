@@ -0,0 +1,204 @@
+use crate::proto::Palette;
+use image::DynamicImage;
+use std::collections::HashMap;
+
+// Plenty to find the main colors, and quick to go through
+const SAMPLE_SIZE: u32 = 64;
+// Below this a color reads as grey
+const MIN_VIBRANT_SATURATION: f32 = 0.35;
+
+/// A group of similar colors in the cover
+struct Swatch {
+    population: u32,
+    rgb: [u8; 3],
+    saturation: f32,
+    lightness: f32,
+}
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+/// Saturation and lightness from HSL, both from 0 to 1
+fn saturation_lightness(rgb: [u8; 3]) -> (f32, f32) {
+    let [r, g, b] = rgb.map(|x| x as f32 / 255.);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.;
+    let saturation = if max == min {
+        0.
+    } else {
+        (max - min) / (1. - (2. * lightness - 1.).abs())
+    };
+    (saturation, lightness)
+}
+
+/// How bright a color looks, as defined by WCAG
+fn luminance(rgb: [u8; 3]) -> f32 {
+    let [r, g, b] = rgb.map(|x| {
+        let x = x as f32 / 255.;
+        if x <= 0.03928 {
+            x / 12.92
+        } else {
+            ((x + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+fn swatches(image: &DynamicImage) -> Vec<Swatch> {
+    // Colors within 16 of each other on every channel count as the same
+    let mut buckets = HashMap::<[u8; 3], (u32, [u32; 3])>::new();
+    for pixel in image
+        .thumbnail(SAMPLE_SIZE, SAMPLE_SIZE)
+        .to_rgba8()
+        .pixels()
+    {
+        let [r, g, b, a] = pixel.0;
+        if a < 128 {
+            continue;
+        }
+
+        let (population, sum) = buckets.entry([r >> 4, g >> 4, b >> 4]).or_default();
+        *population += 1;
+        sum[0] += r as u32;
+        sum[1] += g as u32;
+        sum[2] += b as u32;
+    }
+
+    buckets
+        .into_values()
+        .map(|(population, sum)| {
+            let rgb = sum.map(|x| (x / population) as u8);
+            let (saturation, lightness) = saturation_lightness(rgb);
+            Swatch {
+                population,
+                rgb,
+                saturation,
+                lightness,
+            }
+        })
+        .collect()
+}
+
+/// Picks the colors to theme the UI with, like phone lock screens do
+pub fn extract(image: &DynamicImage) -> Palette {
+    let swatches = swatches(image);
+    let Some(dominant) = swatches.iter().max_by_key(|x| x.population) else {
+        return Palette::default();
+    };
+
+    // Common and colorful, but not so dark or light that the color is lost
+    let vibrant = swatches
+        .iter()
+        .filter(|x| x.saturation >= MIN_VIBRANT_SATURATION && (0.3..=0.8).contains(&x.lightness))
+        .max_by(|a, b| {
+            let score = |x: &Swatch| x.population as f32 * x.saturation;
+            score(a).total_cmp(&score(b))
+        })
+        .unwrap_or(dominant);
+
+    let muted = swatches
+        .iter()
+        .filter(|x| x.saturation < MIN_VIBRANT_SATURATION && (0.2..=0.7).contains(&x.lightness))
+        .max_by_key(|x| x.population)
+        .unwrap_or(dominant);
+
+    // The panel is drawn on the muted color. 0.179 is where black and white have the same contrast
+    let foreground = if luminance(muted.rgb) > 0.179 {
+        [0, 0, 0]
+    } else {
+        [255, 255, 255]
+    };
+
+    Palette {
+        dominant: hex(dominant.rgb),
+        vibrant: hex(vibrant.rgb),
+        muted: hex(muted.rgb),
+        foreground: hex(foreground),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// Each color filling as many columns of a 64px square as given
+    fn stripes(colors: &[([u8; 4], u32)]) -> DynamicImage {
+        let mut image = RgbaImage::new(SAMPLE_SIZE, SAMPLE_SIZE);
+        let mut x = 0;
+        for (color, width) in colors {
+            for column in x..x + width {
+                for y in 0..SAMPLE_SIZE {
+                    image.put_pixel(column, y, Rgba(*color));
+                }
+            }
+            x += width;
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 0.01 && (a.1 - b.1).abs() < 0.01
+    }
+
+    #[test]
+    fn saturation_and_lightness() {
+        assert!(close(saturation_lightness([0, 0, 0]), (0., 0.)));
+        assert!(close(saturation_lightness([255, 255, 255]), (0., 1.)));
+        assert!(close(saturation_lightness([128, 128, 128]), (0., 0.5)));
+        assert!(close(saturation_lightness([255, 0, 0]), (1., 0.5)));
+        assert!(close(saturation_lightness([128, 0, 0]), (1., 0.25)));
+        assert!(close(saturation_lightness([191, 64, 64]), (0.5, 0.5)));
+    }
+
+    #[test]
+    fn picks_colors_by_role() {
+        let palette = extract(&stripes(&[
+            ([200, 30, 30, 255], 40),
+            ([128, 128, 128, 255], 16),
+            ([10, 10, 10, 255], 8),
+        ]));
+
+        assert_eq!(palette.dominant, "#c81e1e");
+        assert_eq!(palette.vibrant, "#c81e1e");
+        assert_eq!(palette.muted, "#808080");
+        // Readable on the grey background
+        assert_eq!(palette.foreground, "#000000");
+    }
+
+    #[test]
+    fn vibrant_prefers_colorful_over_common() {
+        let palette = extract(&stripes(&[
+            ([60, 60, 60, 255], 40),
+            ([30, 200, 30, 255], 24),
+        ]));
+
+        assert_eq!(palette.dominant, "#3c3c3c");
+        assert_eq!(palette.vibrant, "#1ec81e");
+        assert_eq!(palette.muted, "#3c3c3c");
+        assert_eq!(palette.foreground, "#ffffff");
+    }
+
+    #[test]
+    fn falls_back_to_dominant() {
+        let palette = extract(&stripes(&[([0, 0, 0, 255], SAMPLE_SIZE)]));
+        assert_eq!(palette.dominant, "#000000");
+        assert_eq!(palette.vibrant, "#000000");
+        assert_eq!(palette.muted, "#000000");
+        assert_eq!(palette.foreground, "#ffffff");
+    }
+
+    #[test]
+    fn ignores_transparent_pixels() {
+        let palette = extract(&stripes(&[
+            ([255, 255, 255, 0], 60),
+            ([30, 30, 200, 255], 4),
+        ]));
+        assert_eq!(palette.dominant, "#1e1ec8");
+
+        let palette = extract(&stripes(&[([255, 255, 255, 0], SAMPLE_SIZE)]));
+        assert_eq!(palette, Palette::default());
+    }
+}
@@ -0,0 +1,165 @@
+/// What a player was doing the last time we looked
+#[derive(Clone, Debug)]
+pub struct PlayerSnapshot {
+    pub id: String,
+    pub playing: bool,
+    /// Anything that changes along with the track, like its title
+    pub track: String,
+    /// Whether the allow and deny lists let us switch to this player automatically
+    pub followable: bool,
+}
+
+/// Keeps players ordered by when they were last active, like playerctld does, so we can follow
+/// whichever one the user is most likely paying attention to
+#[derive(Default)]
+pub struct PlayerTracker {
+    /// Most recently active first
+    players: Vec<PlayerSnapshot>,
+    /// Picked with `Request::SelectPlayer`, and followed instead for as long as it's around
+    pinned: Option<String>,
+}
+
+impl PlayerTracker {
+    /// Replaces the players we know about, moving any that started playing or changed tracks to the front
+    pub fn update(&mut self, snapshots: Vec<PlayerSnapshot>) {
+        let mut promoted = Vec::new();
+        let mut rest = Vec::new();
+
+        for snapshot in snapshots {
+            match self.players.iter().position(|x| x.id == snapshot.id) {
+                Some(idx) => {
+                    let prev = &self.players[idx];
+                    if (snapshot.playing && !prev.playing) || snapshot.track != prev.track {
+                        promoted.push(snapshot);
+                    } else {
+                        rest.push((idx, snapshot));
+                    }
+                }
+                // Opening a player shouldn't take over, but opening one that's already playing should
+                None if snapshot.playing => promoted.push(snapshot),
+                None => rest.push((usize::MAX, snapshot)),
+            }
+        }
+
+        // Stable, so new players stay in the order they were found in
+        rest.sort_by_key(|(idx, _)| *idx);
+        self.players = promoted
+            .into_iter()
+            .chain(rest.into_iter().map(|(_, x)| x))
+            .collect();
+    }
+
+    pub fn pin(&mut self, id: Option<String>) {
+        self.pinned = id;
+    }
+
+    pub fn pinned(&self) -> Option<&str> {
+        self.pinned.as_deref()
+    }
+
+    /// The player to show and send commands to
+    pub fn active(&self) -> Option<&str> {
+        if let Some(pinned) = &self.pinned
+            && self.players.iter().any(|x| &x.id == pinned)
+        {
+            return Some(pinned);
+        }
+
+        self.players
+            .iter()
+            .find(|x| x.followable)
+            .map(|x| x.id.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(id: &str, playing: bool, track: &str) -> PlayerSnapshot {
+        PlayerSnapshot {
+            id: id.to_string(),
+            playing,
+            track: track.to_string(),
+            followable: true,
+        }
+    }
+
+    #[test]
+    fn follows_newly_playing_player() {
+        let mut tracker = PlayerTracker::default();
+        tracker.update(vec![snapshot("a", true, "1"), snapshot("b", false, "2")]);
+        assert_eq!(tracker.active(), Some("a"));
+
+        tracker.update(vec![snapshot("a", true, "1"), snapshot("b", true, "2")]);
+        assert_eq!(tracker.active(), Some("b"));
+
+        // Pausing it doesn't hand things back
+        tracker.update(vec![snapshot("a", true, "1"), snapshot("b", false, "2")]);
+        assert_eq!(tracker.active(), Some("b"));
+    }
+
+    #[test]
+    fn follows_track_changes() {
+        let mut tracker = PlayerTracker::default();
+        tracker.update(vec![snapshot("a", true, "1"), snapshot("b", false, "2")]);
+        tracker.update(vec![snapshot("a", true, "1"), snapshot("b", false, "3")]);
+        assert_eq!(tracker.active(), Some("b"));
+    }
+
+    #[test]
+    fn opened_player_only_takes_over_if_playing() {
+        let mut tracker = PlayerTracker::default();
+        tracker.update(vec![snapshot("a", false, "1")]);
+        tracker.update(vec![snapshot("a", false, "1"), snapshot("b", false, "2")]);
+        assert_eq!(tracker.active(), Some("a"));
+
+        tracker.update(vec![
+            snapshot("a", false, "1"),
+            snapshot("b", false, "2"),
+            snapshot("c", true, "3"),
+        ]);
+        assert_eq!(tracker.active(), Some("c"));
+    }
+
+    #[test]
+    fn skips_unfollowable_players() {
+        let mut tracker = PlayerTracker::default();
+        let mut denied = snapshot("a", true, "1");
+        denied.followable = false;
+        tracker.update(vec![denied, snapshot("b", false, "2")]);
+        assert_eq!(tracker.active(), Some("b"));
+    }
+
+    #[test]
+    fn pin_and_unpin() {
+        let mut tracker = PlayerTracker::default();
+        tracker.update(vec![snapshot("a", true, "1"), snapshot("b", false, "2")]);
+
+        tracker.pin(Some("b".to_string()));
+        assert_eq!(tracker.pinned(), Some("b"));
+        assert_eq!(tracker.active(), Some("b"));
+
+        // Other players starting doesn't matter while pinned
+        tracker.update(vec![snapshot("a", true, "4"), snapshot("b", false, "2")]);
+        assert_eq!(tracker.active(), Some("b"));
+
+        tracker.pin(None);
+        assert_eq!(tracker.pinned(), None);
+        assert_eq!(tracker.active(), Some("a"));
+    }
+
+    #[test]
+    fn pinned_player_disappearing() {
+        let mut tracker = PlayerTracker::default();
+        tracker.update(vec![snapshot("a", true, "1"), snapshot("b", false, "2")]);
+        tracker.pin(Some("b".to_string()));
+
+        tracker.update(vec![snapshot("a", true, "1")]);
+        assert_eq!(tracker.active(), Some("a"));
+
+        // Still pinned, so it's picked again when it comes back
+        tracker.update(vec![snapshot("a", true, "1"), snapshot("b", false, "2")]);
+        assert_eq!(tracker.active(), Some("b"));
+    }
+}
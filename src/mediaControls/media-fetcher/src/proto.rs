@@ -1,7 +1,12 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::base::compare_floats;
 
+// More than anything shows the art in, so anything past this is a mistake
+const MAX_ART_SIZES: usize = 8;
+// Bigger than any cover is shown, and keeps a request from making us allocate gigabytes
+const MAX_ART_SIZE: u32 = 4096;
+
 #[derive(Deserialize, Serialize, Default, PartialEq, Clone, Debug)]
 pub enum RepeatMode {
     #[default]
@@ -10,17 +15,153 @@ pub enum RepeatMode {
     One,
 }
 
+#[derive(Deserialize, Serialize, Default, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum ArtFormat {
+    #[default]
+    Png,
+    Jpeg,
+    /// Always lossless, it's the only kind the image crate can write
+    Webp,
+}
+
+/// One image to make for `Response::AlbumArt`
+#[derive(Deserialize, Serialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct ArtOptions {
+    /// Largest the width or height can be, art is never scaled up
+    pub max_size: u32,
+    pub format: ArtFormat,
+    /// From 1 to 100, only used for JPEG
+    pub quality: u8,
+}
+
+impl Default for ArtOptions {
+    fn default() -> Self {
+        Self {
+            max_size: 1000,
+            format: ArtFormat::Png,
+            quality: 90,
+        }
+    }
+}
+
+/// For `Request::GetAlbumArt`, keeps the images it asks for to a sensible number and size
+fn deserialize_sizes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ArtOptions>, D::Error> {
+    let mut sizes = Vec::<ArtOptions>::deserialize(deserializer)?;
+    sizes.truncate(MAX_ART_SIZES);
+    for options in &mut sizes {
+        options.max_size = options.max_size.clamp(1, MAX_ART_SIZE);
+    }
+    Ok(sizes)
+}
+
 #[derive(Deserialize)]
 #[serde(tag = "type")]
 pub enum Request {
-    GetAlbumArt,
+    /// Answered with `Response::AlbumArt`
+    GetAlbumArt {
+        /// One image for each, all made from the same decode. If empty, the last sizes asked for
+        /// are used, or a single 1000px PNG. These are also what's sent when the track changes.
+        /// Only the first 8 are used, and `max_size` goes up to 4096
+        #[serde(default, deserialize_with = "deserialize_sizes")]
+        sizes: Vec<ArtOptions>,
+    },
     Play,
     Pause,
+    PlayPause,
+    Stop,
     SkipBackward,
     SkipForward,
-    SetRepeatMode { mode: RepeatMode },
-    SetShuffle { shuffle: bool },
-    Seek { position: f64 },
+    SetRepeatMode {
+        mode: RepeatMode,
+    },
+    SetShuffle {
+        shuffle: bool,
+    },
+    Seek {
+        position: f64,
+    },
+    /// Seeks relative to the current position in seconds, e.g. `-15` to go back 15 seconds
+    SeekBy {
+        offset: f64,
+    },
+    /// Playback speed, e.g. `1.5` for 1.5x
+    // MediaRemote can't change it
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    SetRate {
+        rate: f64,
+    },
+    // Only MPRIS players have their own volume
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    SetVolume {
+        volume: f64,
+    },
+    /// Adds to the volume, e.g. `-0.05` to turn it down by 5%
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    ChangeVolume {
+        delta: f64,
+    },
+    /// Lists the tracks the player has queued up, answered with `Response::TrackList`
+    GetTrackList,
+    /// Jumps to a track from `Response::TrackList`
+    // Only MPRIS players share their queue
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    GoTo {
+        track_id: String,
+    },
+    /// Lists the player's playlists, answered with `Response::Playlists`
+    GetPlaylists,
+    /// Switches to a playlist from `Response::Playlists`
+    // Only MPRIS players share their playlists
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    ActivatePlaylist {
+        id: String,
+    },
+    /// Answered with `Response::ArtCache`
+    GetArtCache,
+    /// Forgets all cached album art, then answers with `Response::ArtCache`
+    ClearArtCache,
+    /// Pins the player with this ID from `Response::Players`,
+    /// or goes back to following the most recently active one if `None`
+    /// (on Windows, the one Windows says is current)
+    SelectPlayer {
+        id: Option<String>,
+    },
+}
+
+/// What the player supports, so the UI can hide controls that would do nothing
+#[derive(Serialize, Default, Clone, Debug, PartialEq)]
+pub struct Capabilities {
+    pub can_play: bool,
+    pub can_pause: bool,
+    pub can_seek: bool,
+    pub can_go_next: bool,
+    pub can_go_previous: bool,
+    pub can_shuffle: bool,
+    pub can_loop: bool,
+    pub can_set_rate: bool,
+    /// If this is false, none of the other controls work either
+    pub can_control: bool,
+}
+
+impl Capabilities {
+    /// For platforms that can't tell, so nothing gets hidden
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn all() -> Self {
+        Self {
+            can_play: true,
+            can_pause: true,
+            can_seek: true,
+            can_go_next: true,
+            can_go_previous: true,
+            can_shuffle: true,
+            can_loop: true,
+            can_set_rate: true,
+            can_control: true,
+        }
+    }
 }
 
 #[derive(Serialize, Default, Clone, Debug)]
@@ -37,23 +178,178 @@ pub struct PlaybackStatus {
     pub shuffle: bool,
     pub track_number: i32,
     pub total_tracks: i32,
+    pub genre: String,
+    /// 0 if unknown, like the track and disc numbers
+    pub year: i32,
+    pub disc_number: i32,
+    pub total_discs: i32,
+    pub composer: String,
+    pub lyricist: String,
+    /// Where the track is playing from, e.g. a file or web page
+    pub url: String,
+    /// Platform specific, matches `TrackInfo::id` on Linux
+    pub track_id: String,
+    /// From 0 to 1, or `None` if the user hasn't rated it
+    pub user_rating: Option<f64>,
+    /// From 0 to 1, or `None` if the player doesn't have its own volume
+    pub volume: Option<f64>,
+    /// Playback speed, 1 being normal
+    pub rate: f64,
+    pub capabilities: Capabilities,
+    /// Identifies the track across platforms, to tell which one `Response::AlbumArt` belongs to.
+    /// Empty if nothing is playing
+    pub track_key: String,
+    /// BlurHash of the cover, to show until `Response::AlbumArt` for this track arrives.
+    /// Only in the status sent when the track changes, and only if it was cached,
+    /// otherwise it follows in `Response::ArtPlaceholder`
+    pub art_placeholder: Option<String>,
 }
 
 impl PartialEq for PlaybackStatus {
     fn eq(&self, other: &Self) -> bool {
         self.title == other.title
+            && self.track_key == other.track_key
+            && self.track_id == other.track_id
             && self.artist == other.artist
             && compare_floats(self.elapsed, other.elapsed)
             && compare_floats(self.duration, other.duration)
             && self.playing == other.playing
             && self.repeat == other.repeat
             && self.shuffle == other.shuffle
+            // Not compare_floats, volume and rate steps can be smaller than its variance
+            && self.volume == other.volume
+            && self.rate == other.rate
+            && self.user_rating == other.user_rating
+            && self.capabilities == other.capabilities
     }
 }
 
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PlayerInfo {
+    /// Platform specific, only meant to be passed back in `Request::SelectPlayer`
+    pub id: String,
+    pub name: String,
+    pub playing: bool,
+    /// Whether this is the player `PlaybackStatus` is about and commands are sent to
+    pub selected: bool,
+    /// Whether this player was picked with `Request::SelectPlayer`, instead of automatically
+    pub pinned: bool,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TrackInfo {
+    /// Platform specific, only meant to be passed back in `Request::GoTo`
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: f64,
+    /// Whether this is the track `PlaybackStatus` is about
+    pub current: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PlaylistInfo {
+    /// Platform specific, only meant to be passed back in `Request::ActivatePlaylist`
+    pub id: String,
+    pub name: String,
+    /// URI of an image, empty if the playlist doesn't have one
+    pub icon: String,
+    /// Whether this is the playlist that's playing
+    pub active: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ArtImage {
+    #[serde(flatten)]
+    pub options: ArtOptions,
+    /// A data URL, a link to the art server with `--serve-art`, or a web URL as is if the player gave us one
+    pub data: String,
+}
+
+/// Colors picked from a cover, all as `#rrggbb`
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Debug)]
+pub struct Palette {
+    /// The most common color
+    pub dominant: String,
+    /// A common colorful one, e.g. for a progress bar
+    pub vibrant: String,
+    /// A common greyish one, e.g. for a background
+    pub muted: String,
+    /// Black or white, whichever is readable on `muted`
+    pub foreground: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ArtCacheInfo {
+    /// Images kept in memory, one per size of each cover
+    pub entries: usize,
+    /// Bytes of the in-memory images
+    pub size: u64,
+    pub disk_entries: usize,
+    pub disk_size: u64,
+    /// `None` if art is only cached in memory
+    pub directory: Option<String>,
+}
+
 #[derive(Serialize)]
 #[serde(tag = "type")]
 pub enum Response {
-    AlbumArt { data: String },
-    PlaybackStatus(PlaybackStatus),
+    /// Sent when asked for and whenever the track changes
+    AlbumArt {
+        /// In the same order as `Request::GetAlbumArt::sizes`
+        images: Vec<ArtImage>,
+        /// `PlaybackStatus::track_key` of the track this is for, it may have changed since
+        track_key: String,
+        /// Stays the same for the same picture, e.g. across an album
+        hash: String,
+        /// `None` if we couldn't get the picture ourselves, e.g. a web URL that failed to download
+        palette: Option<Palette>,
+    },
+    /// Sent when the track changes, before its `AlbumArt`, if the status couldn't include one
+    ArtPlaceholder {
+        /// `PlaybackStatus::track_key` of the track this is for, it may have changed since
+        track_key: String,
+        /// See `PlaybackStatus::art_placeholder`
+        placeholder: String,
+    },
+    PlaybackStatus(Box<PlaybackStatus>),
+    Players {
+        players: Vec<PlayerInfo>,
+    },
+    /// Sent when asked for and whenever the queue changes, empty if the player doesn't share it
+    TrackList {
+        tracks: Vec<TrackInfo>,
+    },
+    /// Empty if the player doesn't share its playlists
+    Playlists {
+        playlists: Vec<PlaylistInfo>,
+    },
+    ArtCache(ArtCacheInfo),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn art_sizes_are_limited() {
+        let sizes = (0..20)
+            .map(|x| format!(r#"{{"max_size":{}}}"#, x * 1000))
+            .collect::<Vec<_>>()
+            .join(",");
+        let request = format!(r#"{{"type":"GetAlbumArt","sizes":[{sizes}]}}"#);
+
+        let Ok(Request::GetAlbumArt { sizes }) = serde_json::from_str(&request) else {
+            panic!("Not parsed as GetAlbumArt");
+        };
+        let max_sizes = sizes.iter().map(|x| x.max_size).collect::<Vec<_>>();
+        assert_eq!(max_sizes, [1, 1000, 2000, 3000, 4000, 4096, 4096, 4096]);
+
+        let Ok(Request::GetAlbumArt { sizes }) = serde_json::from_str(r#"{"type":"GetAlbumArt"}"#)
+        else {
+            panic!("Not parsed as GetAlbumArt");
+        };
+        assert!(sizes.is_empty());
+    }
 }
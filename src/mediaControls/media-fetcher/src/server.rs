@@ -0,0 +1,147 @@
+use crate::art::{self, ArtCache};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+};
+
+// Plenty for a GET line and the headers a browser sends
+const MAX_REQUEST_SIZE: usize = 8192;
+// Browsers send the whole request at once, anything slower is holding a connection open
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Discord only loads a couple of images at a time, further connections wait their turn
+const MAX_CONNECTIONS: usize = 16;
+
+/// Serves album art on localhost so it doesn't have to go through stdout as base64.
+/// Every URL starts with a random token, so other programs and websites can't read what's playing:
+/// - `/<token>/art/<file>` is one size of a cover, and never changes
+/// - `/<token>/current/<index>` is whatever was sent last, in the size at `index` of what was asked for
+pub async fn start(art: Arc<ArtCache>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+
+    let mut token = [0u8; 16];
+    getrandom::fill(&mut token)?;
+    let token = token.iter().map(|x| format!("{x:02x}")).collect::<String>();
+
+    art.set_server(format!(
+        "http://127.0.0.1:{}/{}",
+        listener.local_addr()?.port(),
+        token
+    ));
+
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    tokio::spawn(async move {
+        loop {
+            let Ok(permit) = connections.clone().acquire_owned().await else {
+                break;
+            };
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+
+            let art = art.clone();
+            let token = token.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, &art, &token).await {
+                    eprintln!("Error serving album art: {:?}", e);
+                }
+                drop(permit);
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// Compares every byte however early they differ, so how long it takes doesn't give the token away
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Everything up to the blank line after the headers, or `None` if the client gave up or sent too much
+async fn read_head(stream: &mut TcpStream) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut buf = Vec::new();
+    while !buf.windows(4).any(|x| x == b"\r\n\r\n") {
+        let mut chunk = [0u8; 1024];
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 || buf.len() + read > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+    Ok(Some(buf))
+}
+
+async fn handle(mut stream: TcpStream, art: &ArtCache, token: &str) -> anyhow::Result<()> {
+    let Ok(head) = tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await else {
+        return Ok(());
+    };
+    let Some(buf) = head? else {
+        return Ok(());
+    };
+
+    let request = String::from_utf8_lossy(&buf);
+    let mut parts = request.lines().next().unwrap_or_default().split(' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+
+    if method != "GET" && method != "HEAD" {
+        return respond(&mut stream, "405 Method Not Allowed", &[], 0).await;
+    }
+
+    let path = path.split('?').next().unwrap_or_default();
+    let file = match path
+        .strip_prefix('/')
+        .and_then(|x| x.split_once('/'))
+        .filter(|(x, _)| constant_time_eq(x.as_bytes(), token.as_bytes()))
+        .and_then(|(_, x)| x.split_once('/'))
+    {
+        Some(("art", name)) => art.get_file(name).map(|x| (name.to_string(), x, true)),
+        Some(("current", index)) => index
+            .parse()
+            .ok()
+            .and_then(|x| art.current(x))
+            .and_then(|name| art.get_file(&name).map(|x| (name, x, false))),
+        _ => None,
+    };
+
+    let Some((name, bytes, immutable)) = file else {
+        return respond(&mut stream, "404 Not Found", &[], 0).await;
+    };
+
+    let headers = [
+        ("Content-Type", art::content_type(&name)),
+        (
+            "Cache-Control",
+            // File names include a hash of the cover, so they always have the same picture
+            if immutable {
+                "max-age=31536000, immutable"
+            } else {
+                "no-store"
+            },
+        ),
+        // For fetch() from Discord, the token is what keeps other sites out
+        ("Access-Control-Allow-Origin", "*"),
+    ];
+    respond(&mut stream, "200 OK", &headers, bytes.len()).await?;
+    if method == "GET" {
+        stream.write_all(&bytes).await?;
+    }
+    Ok(())
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    content_length: usize,
+) -> anyhow::Result<()> {
+    let mut head = format!("HTTP/1.1 {status}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!("Content-Length: {content_length}\r\n\r\n"));
+    stream.write_all(head.as_bytes()).await?;
+    Ok(())
+}
@@ -1,17 +1,20 @@
 use crate::{
+    art::{self, ArtCache},
     base::{send_response, MediaFetcher},
-    proto::{PlaybackStatus, Request},
+    config::{Args, PlayerFilter},
+    crop,
+    proto::{Capabilities, PlaybackStatus, PlayerInfo, Request},
 };
 use async_trait::async_trait;
-use base64::Engine;
-use image::{imageops::FilterType::Triangle, GenericImageView, Pixel};
-use std::{io::Cursor, sync::Arc};
+use image::DynamicImage;
+use std::sync::Arc;
 use tokio::sync::Mutex;
 use windows::{
     Media::{
         Control::{
             GlobalSystemMediaTransportControlsSession,
             GlobalSystemMediaTransportControlsSessionManager,
+            GlobalSystemMediaTransportControlsSessionPlaybackControls,
             GlobalSystemMediaTransportControlsSessionPlaybackStatus,
         },
         MediaPlaybackAutoRepeatMode,
@@ -21,12 +24,229 @@ use windows::{
 
 const WHAT_ARE_THEY_DOING_AT_MICROSOFT: f64 = 10_000_000.;
 
-#[derive(Default)]
 pub struct WindowsMediaFetcher {
     session_manager: Option<Arc<Mutex<GlobalSystemMediaTransportControlsSessionManager>>>,
+    filter: PlayerFilter,
+    /// App ID of the session pinned with `Request::SelectPlayer`
+    selected_player: Mutex<Option<String>>,
+    art: Arc<ArtCache>,
+}
+
+/// A session's art as the API gives it, before it's decoded
+struct Thumbnail {
+    app_media_id: String,
+    data: Vec<u8>,
+}
+
+impl Thumbnail {
+    fn key(&self) -> String {
+        // The app is part of the key since Spotify's thumbnails get cropped
+        art::cache_key((&self.app_media_id, &self.data))
+    }
+
+    fn load(&self) -> anyhow::Result<DynamicImage> {
+        // API gives us many formats, the cache turns them into whatever was asked for
+        let image = art::decode(&self.data)?;
+        Ok(crop::crop(image, &self.app_media_id))
+    }
+
+    /// Sends the placeholder first if `with_placeholder`, since it's ready well before the art is
+    fn send(
+        &self,
+        art: &ArtCache,
+        track_key: String,
+        with_placeholder: bool,
+    ) -> anyhow::Result<()> {
+        let key = self.key();
+        if with_placeholder && let Some(placeholder) = art.placeholder(&key, || self.load()) {
+            send_response(crate::proto::Response::ArtPlaceholder {
+                track_key: track_key.clone(),
+                placeholder,
+            })?;
+        }
+
+        let (images, palette) = art.get_or_insert(&key, &art.sizes(), || self.load())?;
+        send_response(crate::proto::Response::AlbumArt {
+            images,
+            track_key,
+            hash: key,
+            palette: Some(palette),
+        })
+    }
+
+    /// Decoding and resizing can take a while, so this doesn't hold up status updates or commands
+    fn send_in_background(self, art: Arc<ArtCache>, track_key: String, with_placeholder: bool) {
+        std::thread::spawn(move || {
+            if let Err(e) = self.send(&art, track_key, with_placeholder) {
+                eprintln!("Error sending album art: {:?}", e);
+            }
+        });
+    }
+}
+
+fn session_id(session: &GlobalSystemMediaTransportControlsSession) -> String {
+    session
+        .SourceAppUserModelId()
+        .map(|x| x.to_string_lossy())
+        .unwrap_or_default()
+}
+
+/// A readable name for the app behind a session ID, e.g. `Spotify` for both `Spotify.exe`
+/// and `SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify`
+fn app_name(id: &str) -> &str {
+    // Store apps are `<publisher>.<package>_<hash>!<app>`, others are usually their executable's path
+    let Some((family, app)) = id.split_once('!') else {
+        let file = id.rsplit(['\\', '/']).next().unwrap_or(id);
+        return match file.rsplit_once('.') {
+            Some((stem, extension)) if extension.eq_ignore_ascii_case("exe") => stem,
+            _ => file,
+        };
+    };
+
+    let app = app.rsplit('.').next().unwrap_or(app);
+    // Plenty of them just call theirs `App`
+    if app.eq_ignore_ascii_case("app") {
+        let package = family.split('_').next().unwrap_or(family);
+        package.rsplit('.').next().unwrap_or(package)
+    } else {
+        app
+    }
 }
 
 impl WindowsMediaFetcher {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            session_manager: None,
+            filter: args.player_filter(),
+            selected_player: Default::default(),
+            art: Arc::new(ArtCache::new(args.art_cache_dir.clone())),
+        }
+    }
+
+    /// Returns every session, and the one to show and send commands to
+    async fn get_sessions(
+        &self,
+    ) -> (
+        Vec<GlobalSystemMediaTransportControlsSession>,
+        Option<GlobalSystemMediaTransportControlsSession>,
+    ) {
+        let manager = self.session_manager.as_ref().unwrap().lock().await;
+        let sessions = manager
+            .GetSessions()
+            .map(|x| x.into_iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        // Fall back to whatever Windows thinks is current if the pinned one went away,
+        // unless we aren't allowed to follow that one. Windows decides which is current,
+        // we don't track the most recently active player like on Linux
+        let follows = |session: &GlobalSystemMediaTransportControlsSession| {
+            let id = session_id(session);
+            self.filter.follows(&[&id, app_name(&id)])
+        };
+        let selected = self.selected_player.lock().await.clone();
+        let session = selected
+            .and_then(|id| sessions.iter().find(|x| session_id(x) == id).cloned())
+            .or_else(|| manager.GetCurrentSession().ok().filter(follows))
+            .or_else(|| sessions.iter().find(|x| follows(x)).cloned());
+
+        (sessions, session)
+    }
+
+    async fn active_session(&self) -> anyhow::Result<GlobalSystemMediaTransportControlsSession> {
+        match self.get_sessions().await.1 {
+            Some(session) => Ok(session),
+            None => anyhow::bail!("No media session is active"),
+        }
+    }
+
+    fn get_players(
+        sessions: &[GlobalSystemMediaTransportControlsSession],
+        current: Option<&GlobalSystemMediaTransportControlsSession>,
+        pinned: Option<&String>,
+    ) -> Vec<PlayerInfo> {
+        let current_id = current.map(session_id);
+        sessions
+            .iter()
+            .map(|session| {
+                let id = session_id(session);
+                PlayerInfo {
+                    name: app_name(&id).to_string(),
+                    playing: session
+                        .GetPlaybackInfo()
+                        .and_then(|x| x.PlaybackStatus())
+                        .is_ok_and(|x| {
+                            x == GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing
+                        }),
+                    selected: current_id.as_ref() == Some(&id),
+                    pinned: pinned == Some(&id),
+                    id,
+                }
+            })
+            .collect()
+    }
+
+    fn get_capabilities(
+        controls: &GlobalSystemMediaTransportControlsSessionPlaybackControls,
+    ) -> Capabilities {
+        let mut capabilities = Capabilities {
+            can_play: controls.IsPlayEnabled().unwrap_or(false),
+            can_pause: controls.IsPauseEnabled().unwrap_or(false),
+            can_seek: controls.IsPlaybackPositionEnabled().unwrap_or(false),
+            can_go_next: controls.IsNextEnabled().unwrap_or(false),
+            can_go_previous: controls.IsPreviousEnabled().unwrap_or(false),
+            can_shuffle: controls.IsShuffleEnabled().unwrap_or(false),
+            can_loop: controls.IsRepeatEnabled().unwrap_or(false),
+            can_set_rate: controls.IsPlaybackRateEnabled().unwrap_or(false),
+            can_control: false,
+        };
+        // There's no such flag, but a session with every button disabled can't be controlled either
+        capabilities.can_control = capabilities != Capabilities::default();
+        capabilities
+    }
+
+    async fn get_thumbnail(
+        session: &GlobalSystemMediaTransportControlsSession,
+    ) -> anyhow::Result<Option<Thumbnail>> {
+        let mut media_properties = None;
+
+        // Try to get the media properties multiple times since thie API returns cryptic errors
+        // (more of that error 0 stuff)
+        for _ in 0..5 {
+            if let Ok(request) = session.TryGetMediaPropertiesAsync() {
+                if let Ok(data) = request.await {
+                    media_properties = Some(data);
+                    break;
+                }
+                break;
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+
+        if media_properties.is_none() {
+            return Ok(None);
+        }
+        let media_properties = media_properties.unwrap();
+
+        let thumbnail = media_properties.Thumbnail()?;
+        let app_media_id = session.SourceAppUserModelId()?.to_string_lossy();
+
+        // Use .get() here to avoid it complaining about future stuff
+        let handle = thumbnail.OpenReadAsync()?.get()?;
+        let stream = handle.GetInputStreamAt(0)?;
+        let reader = DataReader::CreateDataReader(&stream)?;
+
+        // This API sucks so bad
+        reader.LoadAsync(handle.Size()? as u32)?.get()?;
+        let mut buf = vec![0u8; handle.Size()? as usize];
+        reader.ReadBytes(&mut buf)?;
+
+        Ok(Some(Thumbnail {
+            app_media_id,
+            data: buf,
+        }))
+    }
+
     async fn get_status(
         &self,
         session: GlobalSystemMediaTransportControlsSession,
@@ -70,6 +290,26 @@ impl WindowsMediaFetcher {
                 .unwrap_or(false),
             track_number: 0,
             total_tracks: 0,
+            genre: String::new(),
+            year: 0,
+            disc_number: 0,
+            total_discs: 0,
+            composer: String::new(),
+            lyricist: String::new(),
+            url: String::new(),
+            track_id: String::new(),
+            user_rating: None,
+            volume: None,
+            rate: playback_info
+                .PlaybackRate()
+                .and_then(|x| x.Value())
+                .unwrap_or(1.),
+            capabilities: playback_info
+                .Controls()
+                .map(|x| WindowsMediaFetcher::get_capabilities(&x))
+                .unwrap_or_default(),
+            track_key: String::new(),
+            art_placeholder: None,
         };
 
         if let Ok(media_properties) = session.TryGetMediaPropertiesAsync()?.await {
@@ -91,8 +331,18 @@ impl WindowsMediaFetcher {
                 .to_string_lossy();
             new_status.track_number = media_properties.TrackNumber().unwrap_or_default();
             new_status.total_tracks = media_properties.AlbumTrackCount().unwrap_or_default();
+            new_status.genre = media_properties
+                .Genres()
+                .map(|x| {
+                    x.into_iter()
+                        .map(|x| x.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default();
         }
 
+        new_status.track_key = art::track_key(&new_status);
         Ok(new_status)
     }
 }
@@ -107,29 +357,54 @@ impl MediaFetcher for WindowsMediaFetcher {
     }
 
     async fn run(&self) -> anyhow::Result<()> {
-        let manager = self.session_manager.as_ref().unwrap();
         let mut prev_session = PlaybackStatus::default();
+        let mut prev_players = Vec::new();
 
         loop {
-            let new_status = {
-                let manager = manager.lock().await;
-                if let Ok(session) = manager.GetCurrentSession() {
-                    if let Ok(result) = self.get_status(session).await {
-                        // Everything is playing
-                        result
-                    } else {
-                        // Something went wrong, let's pretend nothing is playing
-                        // GetCurrentSession likes to return error 0 ("The operation completed successfully" lmao) sometimes
-                        PlaybackStatus::default()
-                    }
-                } else {
-                    // Nothing is playing
-                    PlaybackStatus::default()
-                }
+            let (sessions, session) = self.get_sessions().await;
+
+            let pinned = self.selected_player.lock().await.clone();
+            let new_players =
+                WindowsMediaFetcher::get_players(&sessions, session.as_ref(), pinned.as_ref());
+            if prev_players != new_players {
+                send_response(crate::proto::Response::Players {
+                    players: new_players.clone(),
+                })?;
+                prev_players = new_players;
+            }
+
+            let new_status = if let Some(session) = &session {
+                // Something went wrong, let's pretend nothing is playing
+                // GetCurrentSession likes to return error 0 ("The operation completed successfully" lmao) sometimes
+                self.get_status(session.clone()).await.unwrap_or_default()
+            } else {
+                // Nothing is playing
+                PlaybackStatus::default()
             };
 
             if prev_session != new_status {
-                send_response(crate::proto::Response::PlaybackStatus(new_status.clone()))?;
+                send_response(crate::proto::Response::PlaybackStatus(Box::new(
+                    new_status.clone(),
+                )))?;
+
+                // The thumbnail can take a few tries to show up, so it's read after the status goes out
+                if prev_session.track_key != new_status.track_key
+                    && !new_status.track_key.is_empty()
+                    && let Some(session) = session.clone()
+                {
+                    let art = self.art.clone();
+                    let track_key = new_status.track_key.clone();
+                    tokio::spawn(async move {
+                        match WindowsMediaFetcher::get_thumbnail(&session).await {
+                            Ok(Some(thumbnail)) => {
+                                thumbnail.send_in_background(art, track_key, true)
+                            }
+                            Ok(None) => {}
+                            Err(e) => eprintln!("Error getting album art: {:?}", e),
+                        }
+                    });
+                }
+
                 prev_session = new_status;
             }
 
@@ -138,134 +413,156 @@ impl MediaFetcher for WindowsMediaFetcher {
         }
     }
 
-    async fn handle_command(&self, request: Request) -> anyhow::Result<()> {
-        let session = self
-            .session_manager
-            .as_ref()
-            .unwrap()
-            .lock()
-            .await
-            .GetCurrentSession()?;
+    fn art_cache(&self) -> Arc<ArtCache> {
+        self.art.clone()
+    }
 
+    async fn handle_command(&self, request: Request) -> anyhow::Result<()> {
         match request {
-            Request::GetAlbumArt => {
-                let mut media_properties = None;
-
-                // Try to get the media properties multiple times since thie API returns cryptic errors
-                // (more of that error 0 stuff)
-                for _ in 0..5 {
-                    if let Ok(request) = session.TryGetMediaPropertiesAsync() {
-                        if let Ok(data) = request.await {
-                            media_properties = Some(data);
-                            break;
-                        }
-                        break;
-                    }
-
-                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            Request::GetAlbumArt { sizes } => {
+                // Even if there's nothing to send yet, so art sent on the next track change has these sizes
+                self.art.set_sizes(sizes);
+                let session = self.active_session().await?;
+                let track_key = self.get_status(session.clone()).await?.track_key;
+                if let Some(thumbnail) = WindowsMediaFetcher::get_thumbnail(&session).await? {
+                    thumbnail.send_in_background(self.art.clone(), track_key, false);
                 }
+            }
 
-                if media_properties.is_none() {
-                    return Ok(());
-                }
-                let media_properties = media_properties.unwrap();
-
-                let thumbnail = media_properties.Thumbnail()?;
-                let app_media_id = session.SourceAppUserModelId()?.to_string_lossy();
-
-                // Use .get() here to avoid it complaining about future stuff
-                let handle = thumbnail.OpenReadAsync()?.get()?;
-                let stream = handle.GetInputStreamAt(0)?;
-                let reader = DataReader::CreateDataReader(&stream)?;
-
-                // This API sucks so bad
-                reader.LoadAsync(handle.Size()? as u32)?.get()?;
-                let mut buf = vec![0u8; handle.Size()? as usize];
-                reader.ReadBytes(&mut buf)?;
-
-                // API gives us many formats, let's only send PNG
-                let mut image = image::load_from_memory(&buf)?;
-
-                // Spotify free accounts give a watermarked image
-                match app_media_id.as_str() {
-                    "Spotify.exe" | "SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify" => {
-                        let top_left = image.get_pixel(0, 0);
-                        let col = top_left.to_rgba();
-                        if col[0] == 0 && col[1] == 0 && col[2] == 0 && col[3] == 0 {
-                            image = image.crop_imm(34, 1, 233, 233);
-                        }
-                    }
-                    _ => {}
-                }
-
-                if image.width() > 1000 {
-                    let ratio = image.height() as f32 / image.width() as f32;
-                    image = image.resize(1000, (1000_f32 * ratio).ceil() as u32, Triangle);
-                } else if image.height() > 1000 {
-                    let ratio = image.width() as f32 / image.height() as f32;
-                    image = image.resize((1000_f32 * ratio).ceil() as u32, 1000, Triangle);
-                }
-
-                let mut bytes = Vec::new();
-                image.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)?;
-
-                let base64 = base64::prelude::BASE64_STANDARD.encode(&bytes);
-                let base64 = format!("data:image/png;base64,{}", base64);
-                send_response(crate::proto::Response::AlbumArt { data: base64 })?;
-
-                Ok(())
+            Request::Play => {
+                self.active_session().await?.TryPlayAsync()?.await?;
             }
 
-            Request::Play | Request::Pause | Request::SkipBackward | Request::SkipForward => {
-                match request {
-                    Request::Play | Request::Pause => {
-                        let status = session.GetPlaybackInfo()?.PlaybackStatus()?;
-                        if status
-                            == GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing
-                        {
-                            session.TryPauseAsync()?.await?;
-                        } else {
-                            session.TryPlayAsync()?.await?;
-                        }
-                    }
+            Request::Pause => {
+                self.active_session().await?.TryPauseAsync()?.await?;
+            }
 
-                    Request::SkipBackward => {
-                        session.TrySkipPreviousAsync()?.await?;
-                    }
+            Request::PlayPause => {
+                self.active_session()
+                    .await?
+                    .TryTogglePlayPauseAsync()?
+                    .await?;
+            }
 
-                    Request::SkipForward => {
-                        session.TrySkipNextAsync()?.await?;
-                    }
+            Request::Stop => {
+                self.active_session().await?.TryStopAsync()?.await?;
+            }
 
-                    _ => unreachable!(),
-                }
+            Request::SkipBackward => {
+                self.active_session().await?.TrySkipPreviousAsync()?.await?;
+            }
 
-                Ok(())
+            Request::SkipForward => {
+                self.active_session().await?.TrySkipNextAsync()?.await?;
             }
 
             Request::SetRepeatMode { mode } => {
-                session
+                self.active_session()
+                    .await?
                     .TryChangeAutoRepeatModeAsync(match mode {
                         crate::proto::RepeatMode::None => MediaPlaybackAutoRepeatMode::None,
                         crate::proto::RepeatMode::All => MediaPlaybackAutoRepeatMode::List,
                         crate::proto::RepeatMode::One => MediaPlaybackAutoRepeatMode::Track,
                     })?
                     .await?;
-                Ok(())
             }
 
             Request::SetShuffle { shuffle } => {
-                session.TryChangeShuffleActiveAsync(shuffle)?.await?;
-                Ok(())
+                self.active_session()
+                    .await?
+                    .TryChangeShuffleActiveAsync(shuffle)?
+                    .await?;
             }
 
             Request::Seek { position } => {
                 let position = position * WHAT_ARE_THEY_DOING_AT_MICROSOFT;
-                session
+                self.active_session()
+                    .await?
                     .TryChangePlaybackPositionAsync(position as i64)?
                     .await?;
-                Ok(())
+            }
+
+            Request::SeekBy { offset } => {
+                let session = self.active_session().await?;
+                let position = session.GetTimelineProperties()?.Position()?.Duration as f64
+                    + offset * WHAT_ARE_THEY_DOING_AT_MICROSOFT;
+                session
+                    .TryChangePlaybackPositionAsync(position.max(0.) as i64)?
+                    .await?;
+            }
+
+            Request::SetRate { rate } => {
+                // Same as on Linux, a rate of 0 would just be pausing
+                anyhow::ensure!(rate > 0., "Rate has to be positive");
+                self.active_session()
+                    .await?
+                    .TryChangePlaybackRateAsync(rate)?
+                    .await?;
+            }
+
+            Request::SetVolume { .. } | Request::ChangeVolume { .. } => {
+                anyhow::bail!("Media sessions don't have their own volume")
+            }
+
+            Request::GetTrackList => {
+                // Media sessions don't share their queue
+                send_response(crate::proto::Response::TrackList { tracks: Vec::new() })?;
+            }
+
+            Request::GoTo { .. } => {
+                anyhow::bail!("Media sessions don't share their queue")
+            }
+
+            Request::GetPlaylists => {
+                // Media sessions don't share their playlists either
+                send_response(crate::proto::Response::Playlists {
+                    playlists: Vec::new(),
+                })?;
+            }
+
+            Request::ActivatePlaylist { .. } => {
+                anyhow::bail!("Media sessions don't share their playlists")
+            }
+
+            Request::SelectPlayer { id } => {
+                *self.selected_player.lock().await = id;
+            }
+
+            Request::GetArtCache => {
+                send_response(crate::proto::Response::ArtCache(self.art.info()))?;
+            }
+
+            Request::ClearArtCache => {
+                self.art.clear()?;
+                send_response(crate::proto::Response::ArtCache(self.art.info()))?;
             }
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_names() {
+        assert_eq!(app_name("Spotify.exe"), "Spotify");
+        assert_eq!(
+            app_name("SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify"),
+            "Spotify"
+        );
+        assert_eq!(
+            app_name("Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic"),
+            "ZuneMusic"
+        );
+        assert_eq!(
+            app_name("AppleInc.AppleMusicWin_nzyj5cx40ttqa!App"),
+            "AppleMusicWin"
+        );
+        assert_eq!(app_name(r"C:\Program Files\Foo\Bar.EXE"), "Bar");
+        // Browsers often use an ID that means nothing on its own
+        assert_eq!(app_name("308046B0AF4A39CB"), "308046B0AF4A39CB");
     }
 }
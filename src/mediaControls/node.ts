@@ -18,7 +18,16 @@ const natives: MediaControlsNatives = {
     const mediaFetcherPath = moonlightNode.getConfigOption<string>("mediaControls", "mediaFetcherPath");
     if (mediaFetcherPath == null) return;
 
-    mediaFetcherProcess = child_process.spawn(mediaFetcherPath);
+    const args: string[] = [];
+    const allowedPlayers = moonlightNode.getConfigOption<string[]>("mediaControls", "mediaFetcherAllowedPlayers") ?? [];
+    for (const player of allowedPlayers) args.push("--allow-player", player);
+    const ignoredPlayers = moonlightNode.getConfigOption<string[]>("mediaControls", "mediaFetcherIgnoredPlayers") ?? [];
+    for (const player of ignoredPlayers) args.push("--deny-player", player);
+    const artCacheDir = moonlightNode.getConfigOption<string>("mediaControls", "mediaFetcherArtCacheDir");
+    if (artCacheDir) args.push("--art-cache-dir", artCacheDir);
+    if (moonlightNode.getConfigOption<boolean>("mediaControls", "mediaFetcherServeArt")) args.push("--serve-art");
+
+    mediaFetcherProcess = child_process.spawn(mediaFetcherPath, args);
 
     let readBuffer = Buffer.alloc(0);
     mediaFetcherProcess.stdout!.on("data", (data: Buffer) => {
@@ -2,11 +2,24 @@ export enum MediaFetcherRequestType {
   GetAlbumArt = "GetAlbumArt",
   Play = "Play",
   Pause = "Pause",
+  PlayPause = "PlayPause",
+  Stop = "Stop",
   SkipBackward = "SkipBackward",
   SkipForward = "SkipForward",
   SetRepeatMode = "SetRepeatMode",
   SetShuffle = "SetShuffle",
-  Seek = "Seek"
+  Seek = "Seek",
+  SeekBy = "SeekBy",
+  SetRate = "SetRate",
+  SetVolume = "SetVolume",
+  ChangeVolume = "ChangeVolume",
+  GetTrackList = "GetTrackList",
+  GoTo = "GoTo",
+  GetPlaylists = "GetPlaylists",
+  ActivatePlaylist = "ActivatePlaylist",
+  GetArtCache = "GetArtCache",
+  ClearArtCache = "ClearArtCache",
+  SelectPlayer = "SelectPlayer"
 }
 
 export enum RepeatMode {
@@ -15,9 +28,23 @@ export enum RepeatMode {
   One = "One"
 }
 
+export enum ArtFormat {
+  Png = "Png",
+  Jpeg = "Jpeg",
+  Webp = "Webp"
+}
+
+export type MediaArtOptions = {
+  max_size: number;
+  format: ArtFormat;
+  // Only used for JPEG
+  quality: number;
+};
+
 export type MediaFetcherRequest =
   | {
       type: MediaFetcherRequestType.GetAlbumArt;
+      sizes?: MediaArtOptions[];
     }
   | {
       type: MediaFetcherRequestType.Play;
@@ -25,6 +52,12 @@ export type MediaFetcherRequest =
   | {
       type: MediaFetcherRequestType.Pause;
     }
+  | {
+      type: MediaFetcherRequestType.PlayPause;
+    }
+  | {
+      type: MediaFetcherRequestType.Stop;
+    }
   | {
       type: MediaFetcherRequestType.SkipBackward;
     }
@@ -42,16 +75,96 @@ export type MediaFetcherRequest =
   | {
       type: MediaFetcherRequestType.Seek;
       position: number;
+    }
+  | {
+      type: MediaFetcherRequestType.SeekBy;
+      offset: number;
+    }
+  | {
+      type: MediaFetcherRequestType.SetRate;
+      rate: number;
+    }
+  | {
+      type: MediaFetcherRequestType.SetVolume;
+      volume: number;
+    }
+  | {
+      type: MediaFetcherRequestType.ChangeVolume;
+      delta: number;
+    }
+  | {
+      type: MediaFetcherRequestType.GetTrackList;
+    }
+  | {
+      type: MediaFetcherRequestType.GoTo;
+      track_id: string;
+    }
+  | {
+      type: MediaFetcherRequestType.GetPlaylists;
+    }
+  | {
+      type: MediaFetcherRequestType.ActivatePlaylist;
+      id: string;
+    }
+  | {
+      type: MediaFetcherRequestType.GetArtCache;
+    }
+  | {
+      type: MediaFetcherRequestType.ClearArtCache;
+    }
+  | {
+      type: MediaFetcherRequestType.SelectPlayer;
+      id: string | null;
     };
 
 export enum MediaFetcherResponseType {
   AlbumArt = "AlbumArt",
-  PlaybackStatus = "PlaybackStatus"
+  ArtPlaceholder = "ArtPlaceholder",
+  PlaybackStatus = "PlaybackStatus",
+  Players = "Players",
+  TrackList = "TrackList",
+  Playlists = "Playlists",
+  ArtCache = "ArtCache"
 }
 
+export type MediaArtImage = MediaArtOptions & {
+  // A data URL, or a URL to media-fetcher's art server or a website
+  data: string;
+};
+
+// Colors picked from the cover, as #rrggbb
+export type MediaPalette = {
+  dominant: string;
+  vibrant: string;
+  muted: string;
+  // Black or white, whichever is readable on the muted color
+  foreground: string;
+};
+
 export type MediaFetcherResponseAlbumArt = {
   type: MediaFetcherResponseType.AlbumArt;
-  data: string;
+  images: MediaArtImage[];
+  track_key: string;
+  hash: string;
+  palette: MediaPalette | null;
+};
+
+export type MediaFetcherResponseArtPlaceholder = {
+  type: MediaFetcherResponseType.ArtPlaceholder;
+  track_key: string;
+  placeholder: string;
+};
+
+export type MediaCapabilities = {
+  can_play: boolean;
+  can_pause: boolean;
+  can_seek: boolean;
+  can_go_next: boolean;
+  can_go_previous: boolean;
+  can_shuffle: boolean;
+  can_loop: boolean;
+  can_set_rate: boolean;
+  can_control: boolean;
 };
 
 export type MediaFetcherResponsePlaybackStatus = {
@@ -68,9 +181,79 @@ export type MediaFetcherResponsePlaybackStatus = {
   shuffle: boolean;
   track_number: number;
   total_tracks: number;
+  genre: string;
+  year: number;
+  disc_number: number;
+  total_discs: number;
+  composer: string;
+  lyricist: string;
+  url: string;
+  track_id: string;
+  user_rating: number | null;
+  volume: number | null;
+  rate: number;
+  capabilities: MediaCapabilities;
+  track_key: string;
+  // A BlurHash of the cover, only sent when the track changes and it was cached, otherwise it's in ArtPlaceholder
+  art_placeholder: string | null;
+};
+
+export type MediaPlayer = {
+  id: string;
+  name: string;
+  playing: boolean;
+  selected: boolean;
+  pinned: boolean;
+};
+
+export type MediaFetcherResponsePlayers = {
+  type: MediaFetcherResponseType.Players;
+  players: MediaPlayer[];
+};
+
+export type MediaTrack = {
+  id: string;
+  title: string;
+  artist: string;
+  album: string;
+  duration: number;
+  current: boolean;
+};
+
+export type MediaFetcherResponseTrackList = {
+  type: MediaFetcherResponseType.TrackList;
+  tracks: MediaTrack[];
+};
+
+export type MediaPlaylist = {
+  id: string;
+  name: string;
+  icon: string;
+  active: boolean;
+};
+
+export type MediaFetcherResponsePlaylists = {
+  type: MediaFetcherResponseType.Playlists;
+  playlists: MediaPlaylist[];
+};
+
+export type MediaFetcherResponseArtCache = {
+  type: MediaFetcherResponseType.ArtCache;
+  entries: number;
+  size: number;
+  disk_entries: number;
+  disk_size: number;
+  directory: string | null;
 };
 
-export type MediaFetcherResponse = MediaFetcherResponseAlbumArt | MediaFetcherResponsePlaybackStatus;
+export type MediaFetcherResponse =
+  | MediaFetcherResponseAlbumArt
+  | MediaFetcherResponseArtPlaceholder
+  | MediaFetcherResponsePlaybackStatus
+  | MediaFetcherResponsePlayers
+  | MediaFetcherResponseTrackList
+  | MediaFetcherResponsePlaylists
+  | MediaFetcherResponseArtCache;
 
 export type MediaControlsNatives = {
   spawnMediaFetcher: (cb: (response: MediaFetcherResponse) => void) => void;
@@ -86,9 +269,27 @@ export type MediaState = {
   elapsed: number;
   duration: number;
   cover?: string;
+  // A smaller version of the cover for the panel, if the source has one
+  thumbnail?: string;
+  palette?: MediaPalette;
+  // A blurry image to show until the cover arrives
+  placeholder?: string;
   playing: boolean;
   repeat: RepeatMode;
   shuffle: boolean;
   track_number: number;
   total_tracks: number;
+  genre?: string;
+  year?: number;
+  disc_number?: number;
+  total_discs?: number;
+  composer?: string;
+  lyricist?: string;
+  url?: string;
+  track_id?: string;
+  user_rating?: number;
+  volume?: number;
+  rate?: number;
+  // Everything is assumed to work if missing
+  capabilities?: MediaCapabilities;
 };
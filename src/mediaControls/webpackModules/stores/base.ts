@@ -1,6 +1,6 @@
 import Dispatcher from "@moonlight-mod/wp/discord/Dispatcher";
 import { Store } from "@moonlight-mod/wp/discord/packages/flux";
-import type { MediaState, RepeatMode } from "../../types";
+import type { MediaPlayer, MediaPlaylist, MediaState, MediaTrack, RepeatMode } from "../../types";
 
 export default abstract class MediaControlsBaseStore extends Store<any> {
   constructor(...args: any[]) {
@@ -16,4 +16,33 @@ export default abstract class MediaControlsBaseStore extends Store<any> {
   abstract setRepeatMode(mode: RepeatMode): void;
   abstract setShuffleMode(shuffle: boolean): void;
   abstract seek(time: number): void;
+
+  seekBy(offset: number) {
+    const state = this.getState();
+    if (state != null) this.seek(Math.max(0, state.elapsed + offset));
+  }
+
+  setRate(rate: number) {}
+
+  setVolume(volume: number) {}
+  changeVolume(delta: number) {}
+
+  getPlayers(): MediaPlayer[] {
+    return [];
+  }
+
+  selectPlayer(id: string | null) {}
+
+  getTrackList(): MediaTrack[] {
+    return [];
+  }
+
+  goTo(id: string) {}
+
+  getPlaylists(): MediaPlaylist[] {
+    return [];
+  }
+
+  refreshPlaylists() {}
+  activatePlaylist(id: string) {}
 }
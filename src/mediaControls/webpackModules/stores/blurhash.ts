@@ -0,0 +1,81 @@
+// Decodes the BlurHash media-fetcher sends as a placeholder, see https://blurha.sh
+
+const CHARACTERS = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+// It's stretched to the size of the cover, and there's nothing sharp in it to lose
+const SIZE = 32;
+
+function decode83(str: string) {
+  let value = 0;
+  for (const char of str) {
+    const digit = CHARACTERS.indexOf(char);
+    if (digit === -1) return null;
+    value = value * 83 + digit;
+  }
+  return value;
+}
+
+function srgbToLinear(value: number) {
+  const v = value / 255;
+  return v <= 0.04045 ? v / 12.92 : ((v + 0.055) / 1.055) ** 2.4;
+}
+
+function linearToSrgb(value: number) {
+  const v = Math.max(0, Math.min(1, value));
+  return Math.round((v <= 0.0031308 ? v * 12.92 : 1.055 * v ** (1 / 2.4) - 0.055) * 255);
+}
+
+export function decodeBlurhash(hash: string, size = SIZE): Uint8ClampedArray | null {
+  const sizeFlag = decode83(hash.slice(0, 1));
+  if (sizeFlag == null) return null;
+  const componentsX = (sizeFlag % 9) + 1;
+  const componentsY = Math.floor(sizeFlag / 9) + 1;
+  if (hash.length !== 4 + 2 * componentsX * componentsY) return null;
+
+  const maxValue = ((decode83(hash.slice(1, 2)) ?? 0) + 1) / 166;
+  const dc = decode83(hash.slice(2, 6));
+  if (dc == null) return null;
+
+  const colors = [[srgbToLinear(dc >> 16), srgbToLinear((dc >> 8) & 255), srgbToLinear(dc & 255)]];
+  for (let i = 1; i < componentsX * componentsY; i++) {
+    const ac = decode83(hash.slice(4 + i * 2, 6 + i * 2));
+    if (ac == null) return null;
+    colors.push(
+      [Math.floor(ac / (19 * 19)), Math.floor(ac / 19) % 19, ac % 19].map((x) => {
+        const value = (x - 9) / 9;
+        return Math.sign(value) * value * value * maxValue;
+      })
+    );
+  }
+
+  const pixels = new Uint8ClampedArray(size * size * 4);
+  for (let y = 0; y < size; y++) {
+    for (let x = 0; x < size; x++) {
+      const rgb = [0, 0, 0];
+      for (let j = 0; j < componentsY; j++) {
+        for (let i = 0; i < componentsX; i++) {
+          const basis = Math.cos((Math.PI * x * i) / size) * Math.cos((Math.PI * y * j) / size);
+          const color = colors[i + j * componentsX];
+          for (let c = 0; c < 3; c++) rgb[c] += color[c] * basis;
+        }
+      }
+
+      const index = (x + y * size) * 4;
+      pixels.set([...rgb.map(linearToSrgb), 255], index);
+    }
+  }
+  return pixels;
+}
+
+export function blurhashToDataUrl(hash: string): string | undefined {
+  const pixels = decodeBlurhash(hash);
+  if (pixels == null) return undefined;
+
+  const canvas = document.createElement("canvas");
+  canvas.width = SIZE;
+  canvas.height = SIZE;
+  const context = canvas.getContext("2d");
+  if (context == null) return undefined;
+
+  context.putImageData(new ImageData(pixels, SIZE, SIZE), 0, 0);
+  return canvas.toDataURL();
+}
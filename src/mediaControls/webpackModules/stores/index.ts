@@ -1,6 +1,6 @@
 import Dispatcher from "@moonlight-mod/wp/discord/Dispatcher";
 import { Store } from "@moonlight-mod/wp/discord/packages/flux";
-import type { MediaState, RepeatMode } from "../../types";
+import type { MediaPlayer, MediaPlaylist, MediaState, MediaTrack, RepeatMode } from "../../types";
 import type MediaControlsBaseStore from "./base";
 
 import createMediaFetcherStore from "./mediaFetcher";
@@ -83,6 +83,50 @@ class MediaControlsStore extends Store<any> {
   seek(time: number) {
     this.currentSource?.seek(time);
   }
+
+  seekBy(offset: number) {
+    this.currentSource?.seekBy(offset);
+  }
+
+  setRate(rate: number) {
+    this.currentSource?.setRate(rate);
+  }
+
+  setVolume(volume: number) {
+    this.currentSource?.setVolume(volume);
+  }
+
+  changeVolume(delta: number) {
+    this.currentSource?.changeVolume(delta);
+  }
+
+  getPlayers(): MediaPlayer[] {
+    return this.currentSource?.getPlayers() ?? [];
+  }
+
+  selectPlayer(id: string | null) {
+    this.currentSource?.selectPlayer(id);
+  }
+
+  getTrackList(): MediaTrack[] {
+    return this.currentSource?.getTrackList() ?? [];
+  }
+
+  goTo(id: string) {
+    this.currentSource?.goTo(id);
+  }
+
+  getPlaylists(): MediaPlaylist[] {
+    return this.currentSource?.getPlaylists() ?? [];
+  }
+
+  refreshPlaylists() {
+    this.currentSource?.refreshPlaylists();
+  }
+
+  activatePlaylist(id: string) {
+    this.currentSource?.activatePlaylist(id);
+  }
 }
 
 const mediaControlsStore = new MediaControlsStore();
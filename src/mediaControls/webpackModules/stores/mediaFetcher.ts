@@ -1,20 +1,39 @@
 import {
+  ArtFormat,
+  type MediaArtOptions,
   type MediaControlsNatives,
   MediaFetcherRequestType,
   type MediaFetcherResponsePlaybackStatus,
+  type MediaPalette,
   MediaFetcherResponseType,
+  type MediaPlayer,
+  type MediaPlaylist,
+  type MediaTrack,
   type RepeatMode
 } from "../../types";
 import MediaControlsBaseStore from "./base";
+import { blurhashToDataUrl } from "./blurhash";
 
 const logger = moonlight.getLogger("mediaControls/mediaFetcher");
 const natives: MediaControlsNatives = moonlight.getNatives("mediaControls");
 
+const ART_SIZES: MediaArtOptions[] = [
+  // The panel shows it at 32px, with some room for high DPI screens
+  { max_size: 96, format: ArtFormat.Jpeg, quality: 85 },
+  // For copying and uploading to Rich Presence
+  { max_size: 1000, format: ArtFormat.Png, quality: 90 }
+];
+
 export class MediaControlsMediaFetcherStore extends MediaControlsBaseStore {
   private status: MediaFetcherResponsePlaybackStatus | null = null;
+  private players: MediaPlayer[] = [];
+  private tracks: MediaTrack[] = [];
+  private playlists: MediaPlaylist[] = [];
 
-  private coverSong: [string, string] | null = null;
-  private cover: string | null = null;
+  // media-fetcher sends art on its own whenever the track changes
+  private cover: { trackKey: string; full: string; thumbnail: string; palette: MediaPalette | null } | null = null;
+  // Comes with the first status of a track, later ones for the same track leave it out
+  private placeholder: { trackKey: string; image: string | undefined } | null = null;
 
   constructor() {
     super();
@@ -24,33 +43,51 @@ export class MediaControlsMediaFetcherStore extends MediaControlsBaseStore {
         logger.silly("Received media fetcher response", data);
 
         if (data.type === MediaFetcherResponseType.PlaybackStatus) {
-          const newCoverSong = [data?.title ?? "", data?.artist ?? ""] as [string, string];
-
-          // Reset album art if the song has changed
-          if (this.coverSong != null && this.status != null) {
-            const [title, artist] = this.coverSong;
-            const [newTitle, newArtist] = newCoverSong;
-            if (newTitle !== title || newArtist !== artist) {
-              this.cover = null;
-              this.coverSong = null;
-            }
+          // Asking immediately after the engine starts explodes, so wait for it to say something first.
+          // This also sets the sizes it sends from now on
+          if (this.status == null) {
+            natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.GetAlbumArt, sizes: ART_SIZES });
           }
 
-          this.status = data ?? null;
-
-          // Only make a request for album art if we don't already have it
-          // For some reason asking immediately after the engine starts explodes
-          if (this.coverSong == null) {
-            this.coverSong = newCoverSong;
-            logger.debug("Requesting album art for", this.coverSong);
-            natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.GetAlbumArt });
+          if (data.art_placeholder != null) {
+            this.placeholder = { trackKey: data.track_key, image: blurhashToDataUrl(data.art_placeholder) };
           }
 
+          this.status = data ?? null;
+          this.emitChange();
+        } else if (data.type === MediaFetcherResponseType.ArtPlaceholder) {
+          // Sent when the status couldn't include one, it's useless once the track has been skipped
+          if (data.track_key !== this.status?.track_key) return;
+
+          this.placeholder = { trackKey: data.track_key, image: blurhashToDataUrl(data.placeholder) };
           this.emitChange();
         } else if (data.type === MediaFetcherResponseType.AlbumArt) {
-          logger.debug("Received album art", data);
-          this.cover = data.data;
+          logger.debug("Received album art", data.track_key, data.hash);
+          // The status always goes out first, so this is art for a track that's already been skipped
+          if (data.track_key !== this.status?.track_key) return;
+
+          // Art sent before it got our sizes only has one
+          const images = [...data.images].sort((a, b) => a.max_size - b.max_size);
+          if (images.length === 0) return;
+
+          this.cover = {
+            trackKey: data.track_key,
+            full: images[images.length - 1].data,
+            thumbnail: images[0].data,
+            palette: data.palette
+          };
           this.emitChange();
+        } else if (data.type === MediaFetcherResponseType.Players) {
+          this.players = data.players;
+          this.emitChange();
+        } else if (data.type === MediaFetcherResponseType.TrackList) {
+          this.tracks = data.tracks;
+          this.emitChange();
+        } else if (data.type === MediaFetcherResponseType.Playlists) {
+          this.playlists = data.playlists;
+          this.emitChange();
+        } else if (data.type === MediaFetcherResponseType.ArtCache) {
+          logger.debug("Album art cache", data);
         }
       });
     }
@@ -69,12 +106,28 @@ export class MediaControlsMediaFetcherStore extends MediaControlsBaseStore {
       album_artist: this.status.album_artist,
       elapsed: this.status.elapsed,
       duration: this.status.duration,
-      cover: this.cover ?? undefined,
+      // Art for the previous track can still arrive after the track changes
+      cover: this.cover?.trackKey === this.status.track_key ? this.cover.full : undefined,
+      thumbnail: this.cover?.trackKey === this.status.track_key ? this.cover.thumbnail : undefined,
+      palette: this.cover?.trackKey === this.status.track_key ? (this.cover.palette ?? undefined) : undefined,
+      placeholder: this.placeholder?.trackKey === this.status.track_key ? this.placeholder.image : undefined,
       playing: this.status.playing,
       repeat: this.status.repeat,
       shuffle: this.status.shuffle,
       track_number: this.status.track_number,
-      total_tracks: this.status.total_tracks
+      total_tracks: this.status.total_tracks,
+      genre: this.status.genre,
+      year: this.status.year,
+      disc_number: this.status.disc_number,
+      total_discs: this.status.total_discs,
+      composer: this.status.composer,
+      lyricist: this.status.lyricist,
+      url: this.status.url,
+      track_id: this.status.track_id,
+      user_rating: this.status.user_rating ?? undefined,
+      volume: this.status.volume ?? undefined,
+      rate: this.status.rate,
+      capabilities: this.status.capabilities
     };
   }
 
@@ -85,8 +138,10 @@ export class MediaControlsMediaFetcherStore extends MediaControlsBaseStore {
 
   playPause() {
     if (natives == null) return;
-    // there are two defined but it does the same thing
-    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.Play });
+    // Ask for what the button showed instead of toggling, in case the player changed state on its own since
+    natives.sendMediaFetcherRequest({
+      type: this.status?.playing ? MediaFetcherRequestType.Pause : MediaFetcherRequestType.Play
+    });
     if (this.status != null) {
       this.status.playing = !this.status.playing;
       this.emitChange();
@@ -116,6 +171,68 @@ export class MediaControlsMediaFetcherStore extends MediaControlsBaseStore {
       this.emitChange();
     }
   }
+
+  seekBy(offset: number) {
+    if (natives == null) return;
+    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.SeekBy, offset });
+    if (this.status != null) {
+      this.status.elapsed = Math.max(0, this.status.elapsed + offset);
+      this.emitChange();
+    }
+  }
+
+  setRate(rate: number) {
+    if (natives == null) return;
+    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.SetRate, rate });
+  }
+
+  setVolume(volume: number) {
+    if (natives == null) return;
+    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.SetVolume, volume });
+    if (this.status != null) {
+      this.status.volume = volume;
+      this.emitChange();
+    }
+  }
+
+  changeVolume(delta: number) {
+    if (natives == null) return;
+    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.ChangeVolume, delta });
+  }
+
+  getPlayers() {
+    return this.players;
+  }
+
+  selectPlayer(id: string | null) {
+    if (natives == null) return;
+    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.SelectPlayer, id });
+  }
+
+  getTrackList() {
+    return this.tracks;
+  }
+
+  goTo(id: string) {
+    if (natives == null) return;
+    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.GoTo, track_id: id });
+  }
+
+  getPlaylists() {
+    return this.playlists;
+  }
+
+  refreshPlaylists() {
+    if (natives == null) return;
+    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.GetPlaylists });
+  }
+
+  activatePlaylist(id: string) {
+    if (natives == null) return;
+    natives.sendMediaFetcherRequest({ type: MediaFetcherRequestType.ActivatePlaylist, id });
+    // Ask again so the active one gets updated, players don't always say when it changes
+    this.refreshPlaylists();
+  }
 }
 
 export default function createMediaFetcherStore() {
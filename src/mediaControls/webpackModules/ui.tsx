@@ -16,21 +16,104 @@ import NativeUtils from "@moonlight-mod/wp/discord/utils/NativeUtils";
 import { MediaControlsStore } from "@moonlight-mod/wp/mediaControls_stores";
 import React from "@moonlight-mod/wp/react";
 import spacepack from "@moonlight-mod/wp/spacepack_spacepack";
-import { RepeatMode } from "../types";
+import { type MediaCapabilities, type MediaState, RepeatMode } from "../types";
 import { NextTrackIcon } from "./NextTrackIcon";
 import { PreviousTrackIcon } from "./PreviousTrackIcon";
 
 const ContextMenuActionCreators = spacepack.require("discord/actions/ContextMenuActionCreators");
+const VOLUME_PRESETS = [0, 0.25, 0.5, 0.75, 1];
+const VOLUME_STEP = 0.05;
+const RATE_PRESETS = [0.5, 0.75, 1, 1.25, 1.5, 2];
+const SKIP_SECONDS = 15;
+const MAX_UP_NEXT = 10;
+
 let MediaBar: React.ComponentType<any> & { Types: { DURATION: "DURATION"; VOLUME: "VOLUME" } };
 let PanelButton: typeof import("@moonlight-mod/wp/discord/components/common/PanelButton")["default"];
 
+function can(state: MediaState | null, capability: keyof MediaCapabilities) {
+  return state?.capabilities?.[capability] ?? true;
+}
+
 function MediaControlsContextMenu() {
   const state = useStateFromStores([MediaControlsStore], () => MediaControlsStore.getState());
+  const players = useStateFromStores([MediaControlsStore], () => MediaControlsStore.getPlayers());
+  const tracks = useStateFromStores([MediaControlsStore], () => MediaControlsStore.getTrackList());
+  const playlists = useStateFromStores([MediaControlsStore], () => MediaControlsStore.getPlaylists());
+  const upNext = tracks.slice(tracks.findIndex((track) => track.current) + 1).slice(0, MAX_UP_NEXT);
+
+  // Playlists aren't pushed like the rest, so ask for them whenever the menu opens
+  React.useEffect(() => {
+    MediaControlsStore.refreshPlaylists();
+  }, []);
 
   return (
     <Menu navId="media-controls" onClose={ContextMenuActionCreators.closeContextMenu}>
       <MenuGroup>
-        <MenuItem id="media-controls-repeat" label="Repeat">
+        {players.length > 1 ? (
+          <>
+            <MenuItem id="media-controls-player" label="Player">
+              <MenuRadioItem
+                id="media-controls-player-automatic"
+                label="Most recently active"
+                checked={!players.some((player) => player.pinned)}
+                action={() => {
+                  MediaControlsStore.selectPlayer(null);
+                }}
+              />
+              {players.map((player) => (
+                <MenuRadioItem
+                  key={player.id}
+                  id={`media-controls-player-${player.id}`}
+                  label={player.name}
+                  checked={player.pinned}
+                  action={() => {
+                    MediaControlsStore.selectPlayer(player.id);
+                  }}
+                />
+              ))}
+            </MenuItem>
+            <MenuSeparator />
+          </>
+        ) : null}
+
+        {playlists.length > 0 ? (
+          <>
+            <MenuItem id="media-controls-playlist" label="Playlist">
+              {playlists.map((playlist) => (
+                <MenuRadioItem
+                  key={playlist.id}
+                  id={`media-controls-playlist-${playlist.id}`}
+                  label={playlist.name}
+                  checked={playlist.active}
+                  action={() => {
+                    MediaControlsStore.activatePlaylist(playlist.id);
+                  }}
+                />
+              ))}
+            </MenuItem>
+            <MenuSeparator />
+          </>
+        ) : null}
+
+        {upNext.length > 0 ? (
+          <>
+            <MenuItem id="media-controls-up-next" label="Up Next">
+              {upNext.map((track) => (
+                <MenuItem
+                  key={track.id}
+                  id={`media-controls-up-next-${track.id}`}
+                  label={track.artist !== "" ? `${track.title} • ${track.artist}` : track.title}
+                  action={() => {
+                    MediaControlsStore.goTo(track.id);
+                  }}
+                />
+              ))}
+            </MenuItem>
+            <MenuSeparator />
+          </>
+        ) : null}
+
+        <MenuItem id="media-controls-repeat" label="Repeat" disabled={!can(state, "can_loop")}>
           <MenuRadioItem
             id="media-controls-repeat-none"
             label="None"
@@ -59,13 +142,65 @@ function MediaControlsContextMenu() {
           />
         </MenuItem>
 
+        {can(state, "can_seek") ? (
+          <>
+            <MenuItem
+              id="media-controls-skip-backward"
+              label={`Back ${SKIP_SECONDS} Seconds`}
+              action={() => {
+                MediaControlsStore.seekBy(-SKIP_SECONDS);
+              }}
+            />
+            <MenuItem
+              id="media-controls-skip-forward"
+              label={`Forward ${SKIP_SECONDS} Seconds`}
+              action={() => {
+                MediaControlsStore.seekBy(SKIP_SECONDS);
+              }}
+            />
+          </>
+        ) : null}
+
+        {state?.rate != null && can(state, "can_set_rate") ? (
+          <MenuItem id="media-controls-rate" label={`Speed (${state.rate}x)`}>
+            {RATE_PRESETS.map((rate) => (
+              <MenuRadioItem
+                key={rate}
+                id={`media-controls-rate-${rate * 100}`}
+                label={rate === 1 ? "Normal" : `${rate}x`}
+                checked={state.rate === rate}
+                action={() => {
+                  MediaControlsStore.setRate(rate);
+                }}
+              />
+            ))}
+          </MenuItem>
+        ) : null}
+
         <MenuCheckboxItem
           id="media-controls-shuffle"
           label="Shuffle"
           checked={state?.shuffle ?? false}
+          disabled={!can(state, "can_shuffle")}
           action={() => MediaControlsStore.setShuffleMode(!state?.shuffle)}
         />
 
+        {state?.volume != null ? (
+          <MenuItem id="media-controls-volume" label={`Volume (${Math.round(state.volume * 100)}%)`}>
+            {VOLUME_PRESETS.map((volume) => (
+              <MenuRadioItem
+                key={volume}
+                id={`media-controls-volume-${volume * 100}`}
+                label={volume === 0 ? "Mute" : `${volume * 100}%`}
+                checked={Math.round(state.volume! * 100) === volume * 100}
+                action={() => {
+                  MediaControlsStore.setVolume(volume);
+                }}
+              />
+            ))}
+          </MenuItem>
+        ) : null}
+
         {state?.cover != null ? (
           <>
             <MenuSeparator />
@@ -90,6 +225,7 @@ function MediaControlsUI() {
   }
 
   const disableBar = moonlight.getConfigOption("mediaControls", "disableBar") ?? false;
+  const albumArtColors = moonlight.getConfigOption<boolean>("mediaControls", "albumArtColors") ?? true;
 
   const state = useStateFromStores([MediaControlsStore], () => MediaControlsStore.getState());
   const [realElapsed, setRealElapsed] = React.useState(0);
@@ -134,7 +270,7 @@ function MediaControlsUI() {
   );
   const onDragEnd = React.useCallback(() => {
     setDragging(false);
-    if (state == null) return;
+    if (state == null || !can(state, "can_seek")) return;
     const time = state.duration * seekPercent;
     MediaControlsStore.seek(time);
   }, [setDragging, seekPercent, state, MediaControlsStore]);
@@ -142,17 +278,34 @@ function MediaControlsUI() {
   if (state == null) return;
 
   const artistAndAlbum = `${state.artist}${state.album && state.album !== "" ? ` • ${state.album}` : ""}`;
+  const palette = albumArtColors ? state.palette : undefined;
 
   return (
     <div
-      className="mediaControls"
+      className={palette != null ? "mediaControls mediaControls-themed" : "mediaControls"}
+      style={
+        palette != null
+          ? ({
+              "--mediaControls-dominant": palette.dominant,
+              "--mediaControls-vibrant": palette.vibrant,
+              "--mediaControls-muted": palette.muted,
+              "--mediaControls-foreground": palette.foreground
+            } as React.CSSProperties)
+          : undefined
+      }
       onContextMenu={(event) => {
         event.preventDefault();
         ContextMenuActionCreators.openContextMenu(event, () => <MediaControlsContextMenu />);
       }}
+      onWheel={(event) => {
+        if (state.volume == null) return;
+        MediaControlsStore.changeVolume(event.deltaY < 0 ? VOLUME_STEP : -VOLUME_STEP);
+      }}
     >
       <div className="mediaControls-controls">
-        {state.cover != null ? <img src={state.cover} className="mediaControls-cover" /> : null}
+        {state.cover != null || state.placeholder != null ? (
+          <img src={state.thumbnail ?? state.cover ?? state.placeholder} className="mediaControls-cover" />
+        ) : null}
 
         <div className="mediaControls-labels">
           <Text variant="text-sm/bold" className="mediaControls-label" color="text-default">
@@ -165,13 +318,24 @@ function MediaControlsUI() {
         </div>
 
         <div className="mediaControls-interact">
-          <PanelButton icon={PreviousTrackIcon} tooltipText="Previous" onClick={() => MediaControlsStore.previous()} />
+          <PanelButton
+            icon={PreviousTrackIcon}
+            tooltipText="Previous"
+            disabled={!can(state, "can_go_previous")}
+            onClick={() => MediaControlsStore.previous()}
+          />
           <PanelButton
             icon={state.playing ? PauseIcon : PlayIcon}
             tooltipText={state.playing ? "Pause" : "Play"}
+            disabled={!can(state, state.playing ? "can_pause" : "can_play")}
             onClick={() => MediaControlsStore.playPause()}
           />
-          <PanelButton icon={NextTrackIcon} tooltipText="Next" onClick={() => MediaControlsStore.next()} />
+          <PanelButton
+            icon={NextTrackIcon}
+            tooltipText="Next"
+            disabled={!can(state, "can_go_next")}
+            onClick={() => MediaControlsStore.next()}
+          />
         </div>
       </div>
       {disableBar ? null : (